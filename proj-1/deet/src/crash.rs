@@ -0,0 +1,85 @@
+//! Decoding of the siginfo of SIGSEGV, SIGBUS and SIGFPE for crash reports: what the si_code
+//! means, and whether the faulting instruction was reading, writing or executing.
+
+use iced_x86::{Code, Decoder, DecoderOptions, InstructionInfoFactory, OpAccess};
+use nix::sys::signal::Signal;
+
+/// Signals that mean the program crashed, and get a crash report.
+pub const CRASH_SIGNALS: [Signal; 3] = [Signal::SIGSEGV, Signal::SIGBUS, Signal::SIGFPE];
+
+/// si_code values any signal can have, as listed in sigaction(2).
+const COMMON_CODES: [(i32, &str, &str); 4] = [
+    (0, "SI_USER", "sent by kill"),
+    (0x80, "SI_KERNEL", "sent by the kernel"),
+    (-1, "SI_QUEUE", "sent by sigqueue"),
+    (-6, "SI_TKILL", "sent by tkill"),
+];
+
+const SEGV_CODES: [(i32, &str, &str); 4] = [
+    (1, "SEGV_MAPERR", "address not mapped to object"),
+    (2, "SEGV_ACCERR", "invalid permissions for mapped object"),
+    (3, "SEGV_BNDERR", "failed address bound checks"),
+    (4, "SEGV_PKUERR", "access was denied by memory protection keys"),
+];
+
+const BUS_CODES: [(i32, &str, &str); 5] = [
+    (1, "BUS_ADRALN", "invalid address alignment"),
+    (2, "BUS_ADRERR", "nonexistent physical address"),
+    (3, "BUS_OBJERR", "object-specific hardware error"),
+    (4, "BUS_MCEERR_AR", "hardware memory error consumed on a machine check"),
+    (5, "BUS_MCEERR_AO", "hardware memory error detected in process but not consumed"),
+];
+
+const FPE_CODES: [(i32, &str, &str); 8] = [
+    (1, "FPE_INTDIV", "integer divide by zero"),
+    (2, "FPE_INTOVF", "integer overflow"),
+    (3, "FPE_FLTDIV", "floating-point divide by zero"),
+    (4, "FPE_FLTOVF", "floating-point overflow"),
+    (5, "FPE_FLTUND", "floating-point underflow"),
+    (6, "FPE_FLTRES", "floating-point inexact result"),
+    (7, "FPE_FLTINV", "floating-point invalid operation"),
+    (8, "FPE_FLTSUB", "subscript out of range"),
+];
+
+/// The name and meaning of si_code `code` of `signal`, e.g. ("SEGV_MAPERR", "address not mapped
+/// to object").
+pub fn describe_code(signal: Signal, code: i32) -> Option<(&'static str, &'static str)> {
+    let codes: &[(i32, &str, &str)] = match signal {
+        Signal::SIGSEGV => &SEGV_CODES,
+        Signal::SIGBUS => &BUS_CODES,
+        Signal::SIGFPE => &FPE_CODES,
+        _ => &[],
+    };
+    codes
+        .iter()
+        .chain(COMMON_CODES.iter())
+        .find(|(value, _, _)| *value == code)
+        .map(|(_, name, meaning)| (*name, *meaning))
+}
+
+/// Whether the instruction in `code`, found at `pc`, faulted reading, writing or executing the
+/// memory at `fault_addr`. Returns None if that can't be told.
+pub fn access_kind(code: &[u8], pc: usize, fault_addr: usize) -> Option<&'static str> {
+    if fault_addr == pc {
+        return Some("execute");
+    }
+    let mut decoder = Decoder::new(64, code, DecoderOptions::NONE);
+    decoder.set_ip(pc as u64);
+    let instruction = decoder.decode();
+    if instruction.code() == Code::INVALID {
+        return None;
+    }
+    let mut factory = InstructionInfoFactory::new();
+    let info = factory.info(&instruction);
+    // an instruction that both reads and writes memory counts as writing
+    let mut kind = None;
+    for memory in info.used_memory() {
+        match memory.access() {
+            OpAccess::Write | OpAccess::CondWrite | OpAccess::ReadWrite
+            | OpAccess::ReadCondWrite => return Some("write"),
+            OpAccess::Read | OpAccess::CondRead => kind = Some("read"),
+            _ => {}
+        }
+    }
+    kind
+}
@@ -1,16 +1,71 @@
+use crate::crash::{self, CRASH_SIGNALS};
 use crate::debugger_command::DebuggerCommand;
-use crate::dwarf_data::{DwarfData, Error as DwarfError};
-use crate::inferior::Inferior;
+use crate::dwarf_data::{DwarfData, Encoding, Error as DwarfError, Type, TypeKind};
+use crate::eval::{self, Evaluator, LValue};
+use crate::examine::{ExamineFormat, Examiner, MAX_INSN_LEN};
+use crate::inferior::{self, ForkSettings, Frame, Inferior, WatchAccess, NUM_HW_WATCHPOINTS};
 use crate::inferior::Status;
+use crate::registers::{self, REGISTER_NAMES};
+use crate::signals::{self, SignalTable};
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
 use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::{self, OpenOptions};
+use std::io::Write as _;
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
 use gimli::RawLocListEntry::OffsetPair;
 
+/// How many lines `list` shows at a time
+const LIST_LINES: usize = 10;
+/// How many lines either side of the current one are shown when the inferior stops
+const STOP_CONTEXT: usize = 2;
+
 #[derive(Clone)]
 struct Breakpoint {
+    num: usize,
+    /// What the user asked for, so the breakpoint can be set again in a new program
+    location: String,
+    addr: usize,
+    enabled: bool,
+    hit_count: usize,
+    /// Only stop when this expression is non-zero
+    condition: Option<String>,
+    /// How many more times to continue through this breakpoint instead of stopping
+    ignore_count: usize,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum WatchKind {
+    Write,
+    Read,
+    Access,
+}
+
+/// A watchpoint, numbered together with the breakpoints. It occupies one debug register per
+/// aligned 1, 2, 4 or 8 byte piece of the watched value.
+struct Watchpoint {
+    num: usize,
+    kind: WatchKind,
+    expr: String,
     addr: usize,
-    orig_byte: u8,
+    dtype: Type,
+    old_value: Option<Vec<u8>>,
+    /// The frame owning the watched memory, if it is on the stack
+    scope: Option<WatchScope>,
+    enabled: bool,
+    hit_count: usize,
+}
+
+/// The stack frame a watched local lives in. A breakpoint where it returns to tells us when the
+/// watchpoint goes out of scope.
+struct WatchScope {
+    tid: Pid,
+    cfa: usize,
+    function: Option<String>,
+    /// Where the frame returns to, unless it is the outermost one
+    ret_addr: Option<usize>,
 }
 
 pub struct Debugger {
@@ -19,8 +74,24 @@ pub struct Debugger {
     readline: Editor<()>,
     inferior: Option<Inferior>,
     debug_data: DwarfData,
-    break_points: Vec<usize>,
-    brk_point_map: HashMap<usize, u8>
+    break_points: Vec<Breakpoint>,
+    next_brk_num: usize,
+    brk_point_map: HashMap<usize, u8>,
+    watch_points: Vec<Watchpoint>,
+    examine_format: ExamineFormat,
+    /// Where a bare `x` continues from
+    examine_next: Option<usize>,
+    /// File and first line a bare `list` continues with, or None to list around the current line
+    list_next: Option<(String, usize)>,
+    frames: Vec<Frame>,
+    selected_frame: usize,
+    fork_settings: ForkSettings,
+    signal_table: SignalTable,
+    /// Where crash reports are written as well, set with `set crash-report-file` or
+    /// `--crash-report-file`
+    crash_report_file: Option<String>,
+    /// Number of the thread we last showed, to tell when the stopping thread changes
+    shown_thread: usize,
 }
 
 impl Debugger {
@@ -41,7 +112,18 @@ impl Debugger {
             inferior: None,
             debug_data,
             break_points: Vec::new(),
-            brk_point_map: HashMap::new()
+            next_brk_num: 0,
+            brk_point_map: HashMap::new(),
+            watch_points: Vec::new(),
+            examine_format: ExamineFormat::default(),
+            examine_next: None,
+            list_next: None,
+            frames: Vec::new(),
+            selected_frame: 0,
+            fork_settings: ForkSettings::default(),
+            signal_table: SignalTable::default(),
+            crash_report_file: None,
+            shown_thread: 1,
         }
     }
 
@@ -57,6 +139,7 @@ impl Debugger {
                 std::process::exit(1);
             }
         };
+        note_missing_debug_info(&debug_data, target);
         return debug_data;
     }
 
@@ -64,50 +147,230 @@ impl Debugger {
         loop {
             match self.get_next_command() {
                 DebuggerCommand::Run(args) => {
-                    // kill the exist inferior
-                    if self.inferior.is_some() {
+                    // kill the exist inferior, but leave a process we attached to running, as
+                    // quit does
+                    if self.is_running() && self.inferior.as_ref().unwrap().is_attached() {
+                        self.detach();
+                    } else if self.inferior.is_some() {
                         self.inferior.as_mut().unwrap().kill();
                     }
 
                     if let Some(inferior) =
-                    Inferior::new(&self.target, &args, &self.break_points, &mut self.brk_point_map) {
+                    Inferior::new(&self.target, &args, &mut self.brk_point_map) {
                         // Create the inferior
                         self.inferior = Some(inferior);
+                        self.load_objects();
+                        self.setup_inferior();
                         // TODO (milestone 1): make the inferior run
                         // You may use self.inferior.as_mut().unwrap() to get a mutable reference
                         // to the Inferior object
-                        let status = self.inferior.as_mut().unwrap().continues(&self.brk_point_map).unwrap();
-                        self.print_condition(status)
+                        self.continue_inferior();
                     } else {
                         println!("Error starting subprocess");
                     }
                 }
+                DebuggerCommand::Attach(arg_opt) => {
+                    match arg_opt.as_ref().and_then(|arg| arg.parse::<i32>().ok()) {
+                        Some(pid) => self.attach(Pid::from_raw(pid)),
+                        None => println!("Argument required (process-id to attach)."),
+                    }
+                }
+                DebuggerCommand::Detach => {
+                    if !self.is_running() {
+                        println!("The program is not being run.");
+                        continue;
+                    }
+                    self.detach();
+                }
                 DebuggerCommand::Continue => {
                     if self.inferior.is_none() || self.inferior.as_ref().unwrap().is_exited() {
                         println!("Error continue without running");
                         continue;
                     }
-                    let status = self.inferior.as_mut().unwrap().continues(&self.brk_point_map).unwrap();
-                    self.print_condition(status);
+                    self.continue_inferior();
+                }
+                DebuggerCommand::Next => {
+                    if !self.is_running() {
+                        println!("Error next without running");
+                        continue;
+                    }
+                    if self.step_out_of_unknown_code() {
+                        continue;
+                    }
+                    let break_points = &mut self.break_points;
+                    let debug_data = &self.debug_data;
+                    let status = self.inferior.as_mut().unwrap()
+                        .next(debug_data, &mut self.brk_point_map, &mut |inferior| {
+                            brk_points_should_stop(break_points, debug_data, inferior)
+                        });
+                    self.report_stop(status);
+                }
+                DebuggerCommand::Step => {
+                    if !self.is_running() {
+                        println!("Error step without running");
+                        continue;
+                    }
+                    if self.step_out_of_unknown_code() {
+                        continue;
+                    }
+                    let break_points = &mut self.break_points;
+                    let debug_data = &self.debug_data;
+                    let status = self.inferior.as_mut().unwrap()
+                        .step(debug_data, &mut self.brk_point_map, &mut |inferior| {
+                            brk_points_should_stop(break_points, debug_data, inferior)
+                        });
+                    self.report_stop(status);
+                }
+                DebuggerCommand::Finish => {
+                    if !self.is_running() {
+                        println!("Error finish without running");
+                        continue;
+                    }
+                    self.finish();
                 }
                 DebuggerCommand::Backtrace => {
                     if self.inferior.is_none() || self.inferior.as_ref().unwrap().is_exited() {
                         println!("Error backtrace without running");
                         continue;
                     }
-                    self.inferior.as_ref().unwrap().print_backtrace(&self.debug_data);
+                    self.print_backtrace();
                 }
-                DebuggerCommand::BreakPoint(arg_opt) => {
+                DebuggerCommand::BreakPoint(arg_opt, condition) => {
                     // ADDRESS
                     if arg_opt.is_some() {
                         let arg = arg_opt.unwrap();
-                        self.breakpoint_solover(arg);
+                        self.breakpoint_solover(arg, condition);
                     }else {
-                        println!("Usage: b|break|breakpoint [address|func_name|line_number]");
+                        println!("Usage: b|break|breakpoint [address|func_name|line_number] \
+                                  [if <condition>]");
+                    }
+                }
+                DebuggerCommand::Ignore(num_opt, count_opt) => {
+                    let num = num_opt.map(|num| num.parse::<usize>());
+                    let count = count_opt.map(|count| count.parse::<usize>());
+                    match (num, count) {
+                        (Some(Ok(num)), Some(Ok(count))) => self.set_ignore_count(num, count),
+                        _ => println!("Usage: ignore <breakpoint number> <count>"),
+                    }
+                }
+                DebuggerCommand::Frame(arg_opt) => {
+                    if !self.is_running() {
+                        println!("No stack.");
+                        continue;
+                    }
+                    match arg_opt.map(|arg| arg.parse::<usize>()) {
+                        None => self.select_frame(self.selected_frame),
+                        Some(Ok(index)) => self.select_frame(index),
+                        Some(Err(_)) => println!("Usage: f|frame [level]"),
+                    }
+                }
+                DebuggerCommand::Up(arg_opt) => {
+                    self.move_frame(arg_opt, true);
+                }
+                DebuggerCommand::Down(arg_opt) => {
+                    self.move_frame(arg_opt, false);
+                }
+                DebuggerCommand::Print(arg_opt) => {
+                    match arg_opt {
+                        Some(name) => self.print_variable(&name),
+                        None => println!("Usage: p|print <variable>"),
+                    }
+                }
+                DebuggerCommand::List(arg_opt) => {
+                    self.list_source(arg_opt);
+                }
+                DebuggerCommand::Examine(spec, arg_opt) => {
+                    self.examine_memory(&spec, arg_opt);
+                }
+                DebuggerCommand::Watch(arg_opt) => {
+                    self.set_watchpoint(WatchKind::Write, arg_opt);
+                }
+                DebuggerCommand::RWatch(arg_opt) => {
+                    self.set_watchpoint(WatchKind::Read, arg_opt);
+                }
+                DebuggerCommand::AWatch(arg_opt) => {
+                    self.set_watchpoint(WatchKind::Access, arg_opt);
+                }
+                DebuggerCommand::InfoBreakpoints => {
+                    self.print_breakpoints();
+                }
+                DebuggerCommand::InfoArgs => {
+                    self.print_frame_variables(true);
+                }
+                DebuggerCommand::InfoLocals => {
+                    self.print_frame_variables(false);
+                }
+                DebuggerCommand::InfoRegisters(names) => {
+                    self.print_registers(&names);
+                }
+                DebuggerCommand::InfoThreads => {
+                    if !self.is_running() {
+                        println!("No threads.");
+                        continue;
+                    }
+                    self.print_threads();
+                }
+                DebuggerCommand::InfoSymbol(arg_opt) => {
+                    match arg_opt {
+                        Some(expr) => self.info_symbol(&expr),
+                        None => println!("Argument required (address)."),
+                    }
+                }
+                DebuggerCommand::InfoSignals(names) => {
+                    let mut signals = Vec::new();
+                    for name in &names {
+                        match signals::parse_signal(name) {
+                            Some(signal) => signals.push(signal),
+                            None => println!("Unknown signal {}.", name),
+                        }
+                    }
+                    if signals.len() == names.len() {
+                        self.signal_table.print(&signals);
+                    }
+                }
+                DebuggerCommand::Handle(args) => {
+                    match self.signal_table.handle(&args) {
+                        Ok(signals) => {
+                            self.signal_table.print(&signals);
+                            if let Some(inferior) = self.inferior.as_mut() {
+                                inferior.set_signal_table(self.signal_table.clone());
+                            }
+                        }
+                        Err(err) => println!("{}", err),
+                    }
+                }
+                DebuggerCommand::Thread(arg_opt) => {
+                    if !self.is_running() {
+                        println!("No thread selected.");
+                        continue;
+                    }
+                    self.thread_command(arg_opt);
+                }
+                DebuggerCommand::Set(arg_opt) => {
+                    match arg_opt {
+                        Some(arg) => self.set_command(&arg),
+                        None => println!("Usage: set [var] <variable|$register> = <value>"),
+                    }
+                }
+                DebuggerCommand::Delete(args) => {
+                    for num in self.parse_brk_nums(&args) {
+                        self.delete_breakpoint(num);
+                    }
+                }
+                DebuggerCommand::Disable(args) => {
+                    for num in self.parse_brk_nums(&args) {
+                        self.set_breakpoint_enabled(num, false);
+                    }
+                }
+                DebuggerCommand::Enable(args) => {
+                    for num in self.parse_brk_nums(&args) {
+                        self.set_breakpoint_enabled(num, true);
                     }
                 }
                 DebuggerCommand::Quit => {
-                    if self.inferior.is_some() {
+                    if self.is_running() && self.inferior.as_ref().unwrap().is_attached() {
+                        self.detach();
+                    } else if self.inferior.is_some() {
                         self.inferior.as_mut().unwrap().kill();
                     }
                     return;
@@ -116,37 +379,1268 @@ impl Debugger {
         }
     }
 
-    fn breakpoint_solover(&mut self, arg: String) {
-        let mut address_opt : Option<usize> = None;
+    fn breakpoint_solover(&mut self, arg: String, condition: Option<String>) {
+        if condition.as_ref().map_or(false, |cond| cond.is_empty()) {
+            println!("Argument required (boolean expression).");
+            return;
+        }
+        let arg = match self.qualify_line(&arg) {
+            Ok(arg) => arg,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        let address : usize;
+        match self.resolve_brk_location(&arg) {
+            Ok(addr) => {
+                println!("Set breakpoint {} at {:#x}", self.next_brk_num, addr);
+                address = addr;
+            },
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        }
+        self.break_points.push(Breakpoint {
+            num: self.next_brk_num,
+            location: arg,
+            addr: address,
+            enabled: true,
+            hit_count: 0,
+            condition,
+            ignore_count: 0,
+        });
+        self.next_brk_num += 1;
+        if self.is_running() {
+            if let Err(err) = self.inferior.as_mut().unwrap()
+                .insert_brk_point(address, &mut self.brk_point_map) {
+                println!("Couldn't insert breakpoint at {:#x}: {}", address, err);
+            }
+        }
+    }
+
+    /// Gives a bare line number the file we are stopped in, or main's file if we aren't
+    /// running, so that the breakpoint stays in that file when it is set again on `run`.
+    fn qualify_line(&self, arg: &str) -> Result<String, String> {
+        if arg.parse::<usize>().is_err() {
+            return Ok(arg.to_string());
+        }
+        let (file, _) = self.default_list_location().ok_or("No symbol table is loaded.")?;
+        Ok(format!("{}:{}", file, arg))
+    }
 
+    /// Turns the argument of `break` into an address: `*<address>`, `<file>:<line>`,
+    /// `<file>:<function>` or a function path. Functions are entered past their prologue;
+    /// `*<address>` is taken as it is.
+    fn resolve_brk_location(&self, arg: &str) -> Result<usize, String> {
         if arg.starts_with("*") {
-            address_opt = Debugger::parse_address(&arg[1..]);
-        }else if  arg.parse::<usize>().is_ok() { // line number
-            let line_number = arg.parse::<usize>().unwrap();
-            address_opt = self.debug_data.get_addr_for_line(None, line_number);
-        }else { // probably a function name
-            address_opt = self.debug_data.get_addr_for_function(None, &arg);
+            return Debugger::parse_address(&arg[1..])
+                .ok_or(format!("Can't set the breakpoint with format '{}'", arg));
+        }
+        let (file, spec) = split_location(arg);
+        if let Some(file) = file {
+            if self.debug_data.get_source_path(file).is_none() {
+                return Err(format!("No source file named {}.", file));
+            }
+        }
+        if let Ok(line_number) = spec.parse::<usize>() {
+            let file = file.ok_or(format!("Can't set the breakpoint with format '{}'", arg))?;
+            return self.debug_data.get_addr_for_line(Some(file), line_number)
+                .ok_or(format!("Line {} in file \"{}\" has no code.", line_number, file));
         }
+        self.resolve_function(file, spec).map(|addr| self.debug_data.skip_prologue(addr))
+    }
 
-        let address : usize;
-        match address_opt {
-            Some(addr) => {
-                println!("Set breakpoint {} at {:#x}", self.break_points.len(), addr);
-                address = addr;
+    /// Finds the function a breakpoint names, by its name or the end of its path, like
+    /// `Circle::new`, looking only in `file` if one is given. A path that fits several functions
+    /// has to be made longer.
+    fn resolve_function(&self, file: Option<&str>, path: &str) -> Result<usize, String> {
+        let mut matches = self.debug_data.find_functions(path);
+        if let Some(file) = file {
+            let wanted = self.debug_data.get_source_path(file);
+            matches.retain(|(_, addr)| {
+                self.debug_data.get_line_from_addr(*addr)
+                    .map_or(false, |line| self.debug_data.get_source_path(&line.file) == wanted)
+            });
+        }
+        match (matches.as_slice(), file) {
+            ([], Some(file)) => Err(format!("Function \"{}\" not defined in \"{}\".", path, file)),
+            ([], None) => Err(format!("Function \"{}\" not defined.", path)),
+            ([(_, addr)], _) => Ok(*addr),
+            _ => {
+                let mut err = format!("Function \"{}\" is ambiguous; it could be any of:", path);
+                for (name, addr) in &matches {
+                    write!(err, "\n  {} at {:#x}", name, addr).unwrap();
+                }
+                err.push_str("\nUse more of its path to pick one.");
+                Err(err)
+            }
+        }
+    }
+
+    /// Called after the inferior exec'd: loads the new program's debugging symbols and sets the
+    /// breakpoints again in it. Watchpoints are deleted, as what they watched is gone.
+    fn follow_exec(&mut self) {
+        let pid = self.inferior.as_ref().unwrap().pid();
+        let target = inferior::executable_path(pid);
+        println!("process {} is executing new program: {}", pid, target);
+        // the breakpoint bytes went away with the old image
+        self.brk_point_map.clear();
+        for wp in self.watch_points.drain(..) {
+            println!("Watchpoint {} deleted because the program has changed.", wp.num);
+        }
+        self.arm_watchpoints();
+        if let Err(err) = self.inferior.as_mut().unwrap().run_to_entry() {
+            println!("Error running {} to its entry point: {}", target, err);
+        }
+        match DwarfData::from_file(&target) {
+            Ok(debug_data) => {
+                note_missing_debug_info(&debug_data, &target);
+                self.debug_data = debug_data;
+                self.target = target;
+                self.load_objects();
+            }
+            Err(_) => {
+                // the old program's symbols would only give wrong answers for the new one
+                println!("Could not load debugging symbols from {}", target);
+                self.debug_data = DwarfData::empty(&target);
+                self.target = target;
+                self.load_objects();
+            }
+        }
+    }
+
+    /// Moves the debugging information to where the program is mapped in the inferior, loads
+    /// that of the shared libraries mapped alongside it, and sets the breakpoints again at the
+    /// addresses their locations now resolve to.
+    fn load_objects(&mut self) {
+        let inferior = self.inferior.as_ref().unwrap();
+        let program = inferior::executable_path(inferior.pid());
+        self.debug_data.unload_libraries();
+        for (path, load_address) in inferior.mapped_files() {
+            if path == program {
+                self.debug_data.relocate(load_address);
+            } else {
+                // not everything mapped is an object file, and that's fine
+                let _ = self.debug_data.load_library(&path, load_address);
+            }
+        }
+        for index in 0..self.break_points.len() {
+            let addr_opt = self.resolve_brk_location(&self.break_points[index].location).ok();
+            let bp = &mut self.break_points[index];
+            match addr_opt {
+                Some(addr) => bp.addr = addr,
+                None if bp.enabled => {
+                    println!("Breakpoint {} ({}) not found in the program; disabled.",
+                             bp.num, bp.location);
+                    bp.enabled = false;
+                }
+                None => {}
+            }
+        }
+        for addr in self.enabled_brk_addrs() {
+            if let Err(err) = self.inferior.as_mut().unwrap()
+                .insert_brk_point(addr, &mut self.brk_point_map) {
+                println!("Couldn't insert breakpoint at {:#x}: {}", addr, err);
+            }
+        }
+    }
+
+    /// Also writes crash reports to `path`, like `set crash-report-file`.
+    pub fn set_crash_report_file(&mut self, path: &str) {
+        self.crash_report_file = Some(path.to_string());
+    }
+
+    /// Attaches to the running process `pid`, loading the debugging symbols of its executable if
+    /// it isn't the program we already have.
+    pub fn attach(&mut self, pid: Pid) {
+        if self.is_running() {
+            println!("A program is being debugged already. Kill or detach it first.");
+            return;
+        }
+        let target = inferior::executable_path(pid);
+        if target != self.target {
+            match DwarfData::from_file(&target) {
+                Ok(debug_data) => {
+                    debug_data.print();
+                    note_missing_debug_info(&debug_data, &target);
+                    self.debug_data = debug_data;
+                    self.target = target.clone();
+                }
+                Err(_) => {
+                    println!("Could not load debugging symbols from {}", target);
+                    return;
+                }
+            }
+        }
+        println!("Attaching to program: {}, process {}", target, pid);
+        match Inferior::attach(pid, &mut self.brk_point_map) {
+            Ok((inferior, status)) => {
+                self.inferior = Some(inferior);
+                self.load_objects();
+                self.setup_inferior();
+                self.report_stop(Ok(status));
+            }
+            Err(err) => println!("Could not attach to process {}: {}", pid, err),
+        }
+    }
+
+    /// Removes our breakpoints and watchpoints from the inferior and lets it run on its own.
+    fn detach(&mut self) {
+        let mut inferior = self.inferior.take().unwrap();
+        let pid = inferior.pid();
+        match inferior.detach(&mut self.brk_point_map) {
+            Ok(()) => println!("Detaching from program: {}, process {}", self.target, pid),
+            Err(err) => println!("Error detaching from process {}: {}", pid, err),
+        }
+        self.frames.clear();
+        self.selected_frame = 0;
+    }
+
+    /// Applies the fork settings and watchpoints to a newly started or attached inferior.
+    /// Watched locals are dropped, since the frames they belonged to are gone.
+    fn setup_inferior(&mut self) {
+        let fork_settings = self.fork_settings;
+        self.inferior.as_mut().unwrap().set_fork_settings(fork_settings);
+        self.inferior.as_mut().unwrap().set_signal_table(self.signal_table.clone());
+        self.shown_thread = 1;
+        self.watch_points.retain(|wp| wp.scope.is_none());
+        self.arm_watchpoints();
+        for index in 0..self.watch_points.len() {
+            self.watch_points[index].old_value = self.read_watched(index);
+        }
+    }
+
+    fn apply_fork_settings(&mut self) {
+        let fork_settings = self.fork_settings;
+        if let Some(inferior) = self.inferior.as_mut() {
+            inferior.set_fork_settings(fork_settings);
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        self.inferior.is_some() && !self.inferior.as_ref().unwrap().is_exited()
+    }
+
+    fn enabled_brk_addrs(&self) -> Vec<usize> {
+        self.break_points.iter().filter(|bp| bp.enabled).map(|bp| bp.addr).collect()
+    }
+
+    /// Resumes the inferior until it stops somewhere the user wants to see: breakpoints whose
+    /// condition is false or whose ignore count hasn't run out are passed through.
+    fn continue_inferior(&mut self) {
+        loop {
+            let status = self.inferior.as_mut().unwrap().continues(&self.brk_point_map);
+            if self.inferior.as_mut().unwrap().take_exec() {
+                self.follow_exec();
+                continue;
+            }
+            if status.is_ok() && !self.brk_point_should_stop() && !self.left_watch_scope() {
+                continue;
+            }
+            // like gdb, a write watchpoint only stops when the value actually changes
+            if let Some(index) = self.hit_watchpoint() {
+                let wp = &self.watch_points[index];
+                if wp.kind == WatchKind::Write && self.read_watched(index) == wp.old_value {
+                    continue;
+                }
+            }
+            self.report_stop(status);
+            return;
+        }
+    }
+
+    /// Checks the conditions and ignore counts of the breakpoints the inferior is stopped on,
+    /// counting a hit for each whose condition holds. Returns false if none of them wants to
+    /// stop. Anything other than a breakpoint trap always stops.
+    fn brk_point_should_stop(&mut self) -> bool {
+        brk_points_should_stop(&mut self.break_points, &self.debug_data,
+                               self.inferior.as_ref().unwrap())
+    }
+
+    /// Reports where the inferior stopped and refreshes the stack frames.
+    fn report_stop(&mut self, status: Result<Status, nix::Error>) {
+        let status = match status {
+            Ok(status) => status,
+            Err(err) => {
+                println!("Error resuming the inferior: {}", err);
+                return;
+            }
+        };
+        if self.inferior.as_mut().unwrap().take_exec() {
+            self.follow_exec();
+        }
+        if let Status::Stopped(_, _) = status {
+            let inferior = self.inferior.as_ref().unwrap();
+            let (num, tid) = inferior.current_thread();
+            if num != self.shown_thread {
+                println!("[Switching to thread {} (LWP {})]", num, tid);
+                self.shown_thread = num;
+            }
+        }
+        self.selected_frame = 0;
+        self.frames = match status {
+            Status::Stopped(_, _) => self.inferior.as_ref().unwrap()
+                .backtrace(&self.debug_data).unwrap_or(Vec::new()),
+            _ => Vec::new(),
+        };
+        // deleting watchpoints renumbers the debug register slots, so find the hit one first
+        let hit = self.hit_watchpoint().map(|index| self.watch_points[index].num);
+        if let Status::Stopped(_, _) = status {
+            self.delete_out_of_scope_watchpoints();
+        }
+        let hit_index = hit.and_then(|num| self.watch_points.iter().position(|wp| wp.num == num));
+        if let Some(index) = hit_index {
+            self.report_watchpoint(index);
+        }
+        let crash_signal = match status {
+            Status::Stopped(signal, _) if CRASH_SIGNALS.contains(&signal) => Some(signal),
+            _ => None,
+        };
+        self.print_condition(status);
+        self.list_next = None;
+        if let Some(line) = self.frames.get(0).and_then(|frame| frame.line.clone()) {
+            let first = line.number.saturating_sub(STOP_CONTEXT).max(1);
+            // not every program ships its source, so don't complain about it on every stop
+            let _ = self.print_source(&line.file, first, line.number + STOP_CONTEXT);
+        }
+        if let Some(signal) = crash_signal {
+            self.crash_report(signal);
+        }
+    }
+
+    /// Describes a crash: the fault from the siginfo, then the registers, the backtrace and the
+    /// locals of the innermost frame with debugging info. The report is printed, and appended to
+    /// the crash report file if one is set.
+    fn crash_report(&self, signal: Signal) {
+        let inferior = self.inferior.as_ref().unwrap();
+        let mut report = String::new();
+        writeln!(report, "Crash report for {}, process {}", self.target, inferior.pid()).unwrap();
+        writeln!(report, "Program received signal {}, {}.", signal.as_str(),
+                 signals::description(signal)).unwrap();
+        let regs = inferior.get_regs().ok();
+        if let (Ok(siginfo), Some(regs)) = (inferior.siginfo(), regs) {
+            let code = match crash::describe_code(signal, siginfo.si_code) {
+                Some((name, meaning)) => format!("{} ({})", name, meaning),
+                None => format!("si_code {}", siginfo.si_code),
+            };
+            let fault_addr = unsafe { siginfo.si_addr() } as usize;
+            let pc = regs.rip as usize;
+            let access = inferior.read_original_memory(pc, MAX_INSN_LEN, &self.brk_point_map).ok()
+                .and_then(|insn| crash::access_kind(&insn, pc, fault_addr));
+            match (signal, access) {
+                (Signal::SIGFPE, _) => {
+                    writeln!(report, "Fault at {:#x}: {}", fault_addr, code).unwrap();
+                }
+                (_, Some(access)) => {
+                    writeln!(report, "Invalid {} at address {:#x}: {}", access, fault_addr, code)
+                        .unwrap();
+                }
+                (_, None) => {
+                    writeln!(report, "Invalid access at address {:#x}: {}", fault_addr, code)
+                        .unwrap();
+                }
+            }
+        }
+        if let Some(regs) = regs {
+            writeln!(report, "\nRegisters:").unwrap();
+            for name in REGISTER_NAMES.iter() {
+                let value = registers::get_register(&regs, name).unwrap();
+                writeln!(report, "{}", self.format_register(name, value)).unwrap();
+            }
+        }
+        writeln!(report, "\nBacktrace:").unwrap();
+        for (index, frame) in self.frames.iter().enumerate() {
+            writeln!(report, "#{:<3} {}", index, frame).unwrap();
+        }
+        // the crash may well be inside a library function called with bad arguments
+        let frame = self.frames.iter().enumerate()
+            .find(|(_, frame)| self.debug_data.get_function_for_addr(frame.pc).is_some());
+        if let Some((index, frame)) = frame {
+            writeln!(report, "\nVariables of #{} {}:", index, frame).unwrap();
+            for args in [true, false].iter() {
+                for line in self.frame_variable_lines(frame, *args).unwrap_or(Vec::new()) {
+                    writeln!(report, "{}", line).unwrap();
+                }
+            }
+        }
+        print!("\n{}", report);
+        if let Some(path) = &self.crash_report_file {
+            let written = OpenOptions::new().create(true).append(true).open(path)
+                .and_then(|mut file| writeln!(file, "{}", report));
+            match written {
+                Ok(()) => println!("Crash report written to {}", path),
+                Err(err) => println!("Couldn't write the crash report to {}: {}", path, err),
+            }
+        }
+    }
+
+    /// `next` and `step` in code without line info run until it returns to its caller, as in
+    /// gdb. Returns false if there is line info, so they should step as usual.
+    fn step_out_of_unknown_code(&mut self) -> bool {
+        let frame = match self.frames.get(0) {
+            Some(frame) if frame.line.is_none() => frame.clone(),
+            _ => return false,
+        };
+        let (function, caller) = match (&frame.function, self.frames.get(1)) {
+            (Some(function), Some(caller)) => (function, caller.clone()),
+            _ => {
+                println!("Cannot find bounds of current function");
+                return true;
+            }
+        };
+        println!("Single stepping until exit from function {},", function);
+        println!("which has no line number information.");
+        let break_points = &mut self.break_points;
+        let debug_data = &self.debug_data;
+        let status = self.inferior.as_mut().unwrap()
+            .run_to_return(caller.pc, frame.cfa, &mut self.brk_point_map, &mut |inferior| {
+                brk_points_should_stop(break_points, debug_data, inferior)
+            });
+        self.report_stop(status);
+        true
+    }
+
+    /// Runs until the current function returns, then reports where we ended up and what was
+    /// left in %rax.
+    fn finish(&mut self) {
+        // the caller's frame tells us where we return to, and the selected frame's CFA what the
+        // stack pointer will be once we do
+        let caller = match self.frames.get(self.selected_frame + 1) {
+            Some(caller) => caller.clone(),
+            None => {
+                println!("\"finish\" not meaningful in the outermost frame.");
+                return;
+            }
+        };
+        println!("Run till exit from #{} {}", self.selected_frame, self.frames[self.selected_frame]);
+        let break_points = &mut self.break_points;
+        let debug_data = &self.debug_data;
+        let inferior = self.inferior.as_mut().unwrap();
+        // a user breakpoint whose condition holds stops us early, and is counted on the way
+        let status = inferior.run_to_return(caller.pc, self.frames[self.selected_frame].cfa,
+                                            &mut self.brk_point_map, &mut |inferior| {
+            brk_points_should_stop(break_points, debug_data, inferior)
+        });
+        let returned = inferior.brk_point_hit() == Some(caller.pc);
+        self.report_stop(status);
+        if returned {
+            let rax = self.inferior.as_ref().unwrap().get_regs().map(|regs| regs.rax);
+            if let Ok(rax) = rax {
+                println!("Value returned: rax = {} ({:#x})", rax as i64, rax);
+            }
+        }
+    }
+
+    /// Returns the pc and frame base that variables should be looked up with, from the
+    /// selected frame.
+    fn current_scope(&self) -> Option<(usize, usize)> {
+        let frame = self.frames.get(self.selected_frame)?;
+        Some((frame.pc, frame.cfa))
+    }
+
+    fn print_backtrace(&self) {
+        for (index, frame) in self.frames.iter().enumerate() {
+            let marker = if index == self.selected_frame { "*" } else { " " };
+            println!("{}#{:<3} {}", marker, index, frame);
+        }
+    }
+
+    /// Handles up and down: moves the selection `count` frames towards the caller or callee.
+    fn move_frame(&mut self, arg_opt: Option<String>, up: bool) {
+        if !self.is_running() {
+            println!("No stack.");
+            return;
+        }
+        let count = match arg_opt.map(|arg| arg.parse::<usize>()) {
+            None => 1,
+            Some(Ok(count)) => count,
+            Some(Err(_)) => {
+                println!("Usage: up|down [count]");
+                return;
+            }
+        };
+        if up {
+            if self.selected_frame + 1 >= self.frames.len() {
+                println!("Initial frame selected; you cannot go up.");
+                return;
+            }
+            let index = (self.selected_frame + count).min(self.frames.len() - 1);
+            self.select_frame(index);
+        } else {
+            if self.selected_frame == 0 {
+                println!("Bottom (innermost) frame selected; you cannot go down.");
+                return;
+            }
+            self.select_frame(self.selected_frame.saturating_sub(count));
+        }
+    }
+
+    /// Lists the inferior's threads with where each one is, marking the current one.
+    fn print_threads(&self) {
+        let inferior = self.inferior.as_ref().unwrap();
+        let (current, _) = inferior.current_thread();
+        println!("  {:<4} {:<24} {}", "Id", "Target Id", "Frame");
+        for (num, tid) in inferior.threads() {
+            let marker = if *num == current { '*' } else { ' ' };
+            let target_id = format!("LWP {} \"{}\"", tid, inferior.thread_name(*tid));
+            let frame = match inferior.thread_backtrace(*tid, &self.debug_data) {
+                Ok(frames) if !frames.is_empty() => format!("{}", frames[0]),
+                _ => "??".to_string(),
+            };
+            println!("{} {:<4} {:<24} {}", marker, num, target_id, frame);
+        }
+    }
+
+    /// `thread` shows the current thread, and `thread N` switches to thread N.
+    fn thread_command(&mut self, arg_opt: Option<String>) {
+        let inferior = self.inferior.as_mut().unwrap();
+        let arg = match arg_opt {
+            Some(arg) => arg,
+            None => {
+                let (num, tid) = inferior.current_thread();
+                println!("[Current thread is {} (LWP {})]", num, tid);
+                return;
+            }
+        };
+        let num = match arg.parse::<usize>() {
+            Ok(num) => num,
+            Err(_) => {
+                println!("Invalid thread ID: {}", arg);
+                return;
+            }
+        };
+        let tid = match inferior.select_thread(num) {
+            Some(tid) => tid,
+            None => {
+                println!("Unknown thread {}.", num);
+                return;
+            }
+        };
+        println!("[Switching to thread {} (LWP {})]", num, tid);
+        self.shown_thread = num;
+        self.frames = inferior.backtrace(&self.debug_data).unwrap_or(Vec::new());
+        self.select_frame(0);
+    }
+
+    /// Selects the frame `index` levels up from the innermost one and prints it.
+    fn select_frame(&mut self, index: usize) {
+        if index >= self.frames.len() {
+            println!("No frame at level {}.", index);
+            return;
+        }
+        self.selected_frame = index;
+        println!("#{:<3} {}", index, self.frames[index]);
+        self.list_next = None;
+        if let Some(line) = self.frames[index].line.clone() {
+            let _ = self.print_source(&line.file, line.number, line.number);
+        }
+    }
+
+    /// Handles `list`, `list <line>`, `list <function>` and `list <file>:<line|function>`. A
+    /// bare `list` continues after the previous listing, or lists around the current line.
+    fn list_source(&mut self, arg_opt: Option<String>) {
+        let (file, center) = match arg_opt {
+            None => match self.list_next.take() {
+                Some((file, first)) => (file, first + LIST_LINES / 2),
+                None => match self.default_list_location() {
+                    Some(location) => location,
+                    None => {
+                        println!("No symbol table is loaded.");
+                        return;
+                    }
+                },
+            },
+            Some(arg) => match self.resolve_list_location(&arg) {
+                Ok(location) => location,
+                Err(err) => {
+                    println!("{}", err);
+                    return;
+                }
             },
+        };
+        let first = center.saturating_sub(LIST_LINES / 2).max(1);
+        match self.print_source(&file, first, first + LIST_LINES - 1) {
+            Ok(last) => self.list_next = Some((file, last + 1)),
+            Err(err) => println!("{}", err),
+        }
+    }
+
+    /// The file and line of the selected frame, or of main if we aren't running.
+    fn default_list_location(&self) -> Option<(String, usize)> {
+        if let Some(line) = self.frames.get(self.selected_frame).and_then(|frame| frame.line.as_ref()) {
+            return Some((line.file.clone(), line.number));
+        }
+        let main_addr = self.debug_data.get_addr_for_function(None, "main")?;
+        let line = self.debug_data.get_line_from_addr(main_addr)?;
+        Some((line.file, line.number))
+    }
+
+    /// Turns the argument of `list` into a file and the line to center the listing on.
+    fn resolve_list_location(&self, arg: &str) -> Result<(String, usize), String> {
+        let (file, spec) = split_location(arg);
+        if let Ok(number) = spec.parse::<usize>() {
+            let file = match file {
+                Some(file) => file.to_string(),
+                None => match &self.list_next {
+                    Some((file, _)) => file.clone(),
+                    None => self.default_list_location()
+                        .ok_or("No symbol table is loaded.".to_string())?.0,
+                },
+            };
+            if self.debug_data.get_source_path(&file).is_none() {
+                return Err(format!("No source file named {}.", file));
+            }
+            return Ok((file, number));
+        }
+        let addr = self.resolve_function(file, spec)?;
+        let line = self.debug_data.get_line_from_addr(addr)
+            .ok_or(format!("Function \"{}\" has no line information.", spec))?;
+        Ok((line.file, line.number))
+    }
+
+    /// Prints lines `first` to `last` of `file`, marking the line we are stopped at with `>` and
+    /// lines with breakpoints with `b`. Returns the last line printed.
+    fn print_source(&self, file: &str, first: usize, last: usize) -> Result<usize, String> {
+        let path = self.debug_data.get_source_path(file)
+            .ok_or(format!("No source file named {}.", file))?;
+        let source = fs::read_to_string(&path)
+            .or_else(|err| Err(format!("{}: {}.", path.display(), err)))?;
+        let lines: Vec<&str> = source.lines().collect();
+        if first > lines.len() {
+            return Err(format!("Line number {} out of range; \"{}\" has {} lines.",
+                               first, file, lines.len()));
+        }
+        let same_file = |other: &str| self.debug_data.get_source_path(other).as_ref() == Some(&path);
+        let curr_line = self.frames.get(self.selected_frame)
+            .and_then(|frame| frame.line.as_ref())
+            .filter(|line| same_file(&line.file))
+            .map(|line| line.number);
+        let brk_lines: Vec<usize> = self.enabled_brk_addrs().iter()
+            .filter_map(|addr| self.debug_data.get_line_from_addr(*addr))
+            .filter(|line| same_file(&line.file))
+            .map(|line| line.number)
+            .collect();
+        let last = last.min(lines.len());
+        for number in first..=last {
+            let brk_marker = if brk_lines.contains(&number) { "b" } else { " " };
+            let curr_marker = if curr_line == Some(number) { ">" } else { " " };
+            println!("{}{} {:>4}  {}", brk_marker, curr_marker, number, lines[number - 1]);
+        }
+        Ok(last)
+    }
+
+    fn print_variable(&self, expr: &str) {
+        if !self.is_running() {
+            println!("Error print without running");
+            return;
+        }
+        let (pc, frame_base) = match self.current_scope() {
+            Some(val) => val,
+            None => {
+                println!("No frame selected.");
+                return;
+            }
+        };
+        let inferior = self.inferior.as_ref().unwrap();
+        match Evaluator::new(&self.debug_data, inferior, pc, frame_base).print(expr) {
+            Ok(value) => println!("{} = {}", expr, value),
+            Err(err) => println!("{}", err),
+        }
+    }
+
+    /// Handles `x/NFU addr`. Without an address it continues where the last x left off.
+    fn examine_memory(&mut self, spec: &str, arg_opt: Option<String>) {
+        if !self.is_running() {
+            println!("Error x without running");
+            return;
+        }
+        let fmt = match ExamineFormat::parse(spec, self.examine_format) {
+            Ok(fmt) => fmt,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        let (pc, frame_base) = self.current_scope().unwrap_or((0, 0));
+        let inferior = self.inferior.as_ref().unwrap();
+        let addr = match arg_opt {
+            Some(expr) => {
+                match Evaluator::new(&self.debug_data, inferior, pc, frame_base)
+                    .integer_value(&expr) {
+                    Ok(addr) => addr as usize,
+                    Err(err) => {
+                        println!("{}", err);
+                        return;
+                    }
+                }
+            }
+            None => match self.examine_next {
+                Some(addr) => addr,
+                None => {
+                    println!("Argument required (starting display address).");
+                    return;
+                }
+            },
+        };
+        let curr_pc = inferior.get_regs().ok().map(|regs| regs.rip as usize);
+        let examiner = Examiner::new(&self.debug_data, inferior, &self.brk_point_map, curr_pc);
+        match examiner.examine(addr, &fmt) {
+            Ok(next) => self.examine_next = Some(next),
+            Err(err) => println!("{}", err),
+        }
+        self.examine_format = fmt;
+    }
+
+    /// Prints the arguments or the local variables of the current function.
+    fn print_frame_variables(&self, args: bool) {
+        if !self.is_running() {
+            println!("No frame selected.");
+            return;
+        }
+        let frame = match self.frames.get(self.selected_frame) {
+            Some(frame) => frame,
+            None => {
+                println!("No frame selected.");
+                return;
+            }
+        };
+        match self.frame_variable_lines(frame, args) {
+            Ok(lines) if lines.is_empty() => {
+                println!("{}", if args { "No arguments." } else { "No locals." });
+            }
+            Ok(lines) => {
+                for line in lines {
+                    println!("{}", line);
+                }
+            }
+            Err(err) => println!("{}", err),
+        }
+    }
+
+    /// `name = value` for each argument, or each local, of `frame`.
+    fn frame_variable_lines(&self, frame: &Frame, args: bool) -> Result<Vec<String>, String> {
+        let func = self.debug_data.get_function_for_addr(frame.pc)
+            .ok_or("No symbol table info available.".to_string())?;
+        let vars = if args { &func.args } else { &func.variables };
+        let inferior = self.inferior.as_ref().unwrap();
+        let evaluator = Evaluator::new(&self.debug_data, inferior, frame.pc, frame.cfa);
+        Ok(vars.iter().map(|var| match evaluator.print_variable(var) {
+            Ok(value) => format!("{} = {}", var.name, value),
+            Err(err) => format!("{} = <{}>", var.name, err),
+        }).collect())
+    }
+
+    /// Prints the named registers, or all of them: the value in hex, then in natural form.
+    fn print_registers(&self, names: &Vec<String>) {
+        if !self.is_running() {
+            println!("The program has no registers now.");
+            return;
+        }
+        let regs = match self.inferior.as_ref().unwrap().get_regs() {
+            Ok(regs) => regs,
+            Err(err) => {
+                println!("Couldn't read the registers: {}", err);
+                return;
+            }
+        };
+        let names: Vec<&str> = if names.is_empty() {
+            REGISTER_NAMES.to_vec()
+        } else {
+            names.iter().map(|name| name.trim_start_matches('$')).collect()
+        };
+        for name in names {
+            let value = match registers::get_register(&regs, name) {
+                Some(value) => value,
+                None => {
+                    println!("Invalid register `{}'", name);
+                    continue;
+                }
+            };
+            println!("{}", self.format_register(name, value));
+        }
+    }
+
+    /// A line of `info registers`: the name, the value in hex, then in natural form.
+    fn format_register(&self, name: &str, value: u64) -> String {
+        let natural = match registers::canonical_name(name) {
+            "eflags" => registers::format_eflags(value),
+            "rip" => self.debug_data.get_label_for_addr(value as usize),
+            "rsp" | "rbp" => format!("{:#x}", value),
+            _ => format!("{}", value as i64),
+        };
+        format!("{:<15}{:<19}{}", name, format!("{:#x}", value), natural)
+    }
+
+    /// `info symbol`: the ELF symbol an address is in, and the section and library.
+    fn info_symbol(&self, expr: &str) {
+        let addr = if self.is_running() {
+            let (pc, frame_base) = self.current_scope().unwrap_or((0, 0));
+            let inferior = self.inferior.as_ref().unwrap();
+            Evaluator::new(&self.debug_data, inferior, pc, frame_base).integer_value(expr)
+        } else {
+            eval::parse_number(expr)
+        };
+        // names of functions and variables without debugging info only have a symbol
+        let symbol_addr = self.debug_data.get_addr_for_symbol(expr.trim_start_matches('&').trim());
+        let addr = match (addr, symbol_addr) {
+            (Ok(addr), _) => addr as usize,
+            (Err(_), Some(addr)) => addr,
+            (Err(err), None) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        match self.debug_data.get_elf_symbol_for_addr(addr) {
+            Some((symbol, offset, library)) => {
+                let name = if offset == 0 {
+                    symbol.name.clone()
+                } else {
+                    format!("{} + {}", symbol.name, offset)
+                };
+                match library {
+                    Some(path) => println!("{} in section {} of {}", name, symbol.section, path),
+                    None => println!("{} in section {}", name, symbol.section),
+                }
+            }
+            None => println!("No symbol matches {}.", expr),
+        }
+    }
+
+    /// Handles `set var x = value` (the "var" is optional) and `set $reg = value`.
+    fn set_command(&mut self, arg: &str) {
+        let words: Vec<&str> = arg.split_whitespace().collect();
+        match words.as_slice() {
+            ["follow-fork-mode", mode] => {
+                match *mode {
+                    "parent" => self.fork_settings.follow_child = false,
+                    "child" => self.fork_settings.follow_child = true,
+                    _ => {
+                        println!("Undefined item: \"{}\".", mode);
+                        return;
+                    }
+                }
+                self.apply_fork_settings();
+                return;
+            }
+            ["crash-report-file", path] => {
+                self.crash_report_file = Some(path.to_string());
+                return;
+            }
+            ["crash-report-file"] => {
+                self.crash_report_file = None;
+                return;
+            }
+            ["detach-on-fork", value] => {
+                match *value {
+                    "on" => self.fork_settings.detach_on_fork = true,
+                    "off" => self.fork_settings.detach_on_fork = false,
+                    _ => {
+                        println!("\"on\" or \"off\" expected.");
+                        return;
+                    }
+                }
+                self.apply_fork_settings();
+                return;
+            }
+            _ => {}
+        }
+        let arg = if arg.starts_with("var ") { &arg[4..] } else { arg };
+        let (lhs, rhs) = match arg.find('=') {
+            Some(pos) if !arg[pos + 1..].starts_with('=') => {
+                (arg[..pos].trim(), arg[pos + 1..].trim())
+            }
+            _ => {
+                println!("Usage: set [var] <variable|$register> = <value>");
+                return;
+            }
+        };
+        if !self.is_running() {
+            println!("Error set without running");
+            return;
+        }
+        if !lhs.starts_with('$') {
+            self.set_variable(lhs, rhs);
+            return;
+        }
+        let (pc, frame_base) = self.current_scope().unwrap_or((0, 0));
+        let inferior = self.inferior.as_ref().unwrap();
+        let value = match Evaluator::new(&self.debug_data, inferior, pc, frame_base)
+            .integer_value(rhs) {
+            Ok(value) => value,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        let mut regs = match inferior.get_regs() {
+            Ok(regs) => regs,
+            Err(err) => {
+                println!("Couldn't read the registers: {}", err);
+                return;
+            }
+        };
+        if !registers::set_register(&mut regs, &lhs[1..], value as u64) {
+            println!("Invalid register `{}'", &lhs[1..]);
+            return;
+        }
+        let inferior = self.inferior.as_mut().unwrap();
+        if let Err(err) = inferior.set_regs(regs, &self.brk_point_map) {
+            println!("Couldn't write the registers: {}", err);
+            return;
+        }
+        // a new %rip, %rsp or %rbp changes the stack we see
+        self.frames = inferior.backtrace(&self.debug_data).unwrap_or(Vec::new());
+        self.selected_frame = 0;
+    }
+
+    /// Assigns `rhs` to the variable or memory `lhs` refers to.
+    fn set_variable(&mut self, lhs: &str, rhs: &str) {
+        let (pc, frame_base) = self.current_scope().unwrap_or((0, 0));
+        let inferior = self.inferior.as_ref().unwrap();
+        let evaluator = Evaluator::new(&self.debug_data, inferior, pc, frame_base);
+        let encoded = evaluator.lvalue(lhs)
+            .and_then(|lvalue| Ok((lvalue.addr, evaluator.encode(&lvalue.dtype, rhs)?)));
+        let (addr, bytes) = match encoded {
+            Ok(val) => val,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        if let Err(err) = self.inferior.as_mut().unwrap()
+            .write_memory(addr, &bytes, &mut self.brk_point_map) {
+            println!("Cannot access memory at address {:#x}: {}", addr, err);
+        }
+    }
+
+    /// Turns the arguments of delete/disable/enable into breakpoint numbers. No arguments means
+    /// every breakpoint.
+    fn parse_brk_nums(&self, args: &Vec<String>) -> Vec<usize> {
+        if args.is_empty() {
+            let brk_nums = self.break_points.iter().map(|bp| bp.num);
+            return brk_nums.chain(self.watch_points.iter().map(|wp| wp.num)).collect();
+        }
+        let mut nums = Vec::new();
+        for arg in args {
+            match arg.parse::<usize>() {
+                Ok(num) => nums.push(num),
+                Err(_) => println!("Invalid breakpoint number '{}'", arg),
+            }
+        }
+        nums
+    }
+
+    fn print_breakpoints(&self) {
+        if self.break_points.is_empty() && self.watch_points.is_empty() {
+            println!("No breakpoints or watchpoints.");
+            return;
+        }
+        println!("{:<7} {:<4} {:<18} {:<6} {}", "Num", "Enb", "Address", "Hits", "What");
+        for bp in &self.break_points {
+            let func_name = self.debug_data.get_function_from_addr(bp.addr)
+                .unwrap_or("??".to_string());
+            let what = match self.debug_data.get_line_from_addr(bp.addr) {
+                Some(line) => format!("in {} at {}", func_name, line),
+                None => format!("in {}", func_name),
+            };
+            println!("{:<7} {:<4} {:<#18x} {:<6} {}", bp.num, if bp.enabled { "y" } else { "n" },
+                     bp.addr, bp.hit_count, what);
+            if let Some(condition) = &bp.condition {
+                println!("        stop only if {}", condition);
+            }
+            if bp.ignore_count > 0 {
+                println!("        will ignore next {} crossings of breakpoint", bp.ignore_count);
+            }
+        }
+        for wp in &self.watch_points {
+            let kind = match wp.kind {
+                WatchKind::Write => "hw watchpoint",
+                WatchKind::Read => "read watchpoint",
+                WatchKind::Access => "acc watchpoint",
+            };
+            println!("{:<7} {:<4} {:<#18x} {:<6} {} {}", wp.num, if wp.enabled { "y" } else { "n" },
+                     wp.addr, wp.hit_count, kind, wp.expr);
+        }
+    }
+
+    fn set_ignore_count(&mut self, num: usize, count: usize) {
+        let bp = match self.break_points.iter_mut().find(|bp| bp.num == num) {
+            Some(bp) => bp,
             None => {
-                println!("Can't set the breakpoint with format '{}'", arg);
+                println!("No breakpoint number {}.", num);
                 return;
             }
+        };
+        bp.ignore_count = count;
+        match count {
+            0 => println!("Will stop next time breakpoint {} is reached.", num),
+            1 => println!("Will ignore next crossing of breakpoint {}.", num),
+            _ => println!("Will ignore next {} crossings of breakpoint {}.", count, num),
         }
-        self.break_points.push(address.clone());
-        if !self.inferior.is_none()  && !self.inferior.as_ref().unwrap().is_exited() {
-            let orig_byte = self.inferior.as_mut().unwrap()
-                .write_byte(address.clone(), 0xcc).ok().unwrap();
-            self.brk_point_map.insert(address, orig_byte);
+    }
+
+    fn delete_breakpoint(&mut self, num: usize) {
+        if let Some(index) = self.watch_points.iter().position(|wp| wp.num == num) {
+            self.delete_watchpoint(index);
+            return;
+        }
+        let index = match self.break_points.iter().position(|bp| bp.num == num) {
+            Some(index) => index,
+            None => {
+                println!("No breakpoint number {}.", num);
+                return;
+            }
+        };
+        let bp = self.break_points.remove(index);
+        if bp.enabled {
+            self.unpatch_brk_point(bp.addr);
         }
     }
 
+    fn set_breakpoint_enabled(&mut self, num: usize, enabled: bool) {
+        if let Some(wp) = self.watch_points.iter_mut().find(|wp| wp.num == num) {
+            wp.enabled = enabled;
+            self.arm_watchpoints();
+            return;
+        }
+        let bp = match self.break_points.iter_mut().find(|bp| bp.num == num) {
+            Some(bp) => bp,
+            None => {
+                println!("No breakpoint number {}.", num);
+                return;
+            }
+        };
+        if bp.enabled == enabled {
+            return;
+        }
+        bp.enabled = enabled;
+        let addr = bp.addr;
+        if enabled {
+            if self.is_running() {
+                if let Err(err) = self.inferior.as_mut().unwrap()
+                    .insert_brk_point(addr, &mut self.brk_point_map) {
+                    println!("Couldn't insert breakpoint at {:#x}: {}", addr, err);
+                }
+            }
+        } else {
+            self.unpatch_brk_point(addr);
+        }
+    }
+
+    /// Restores the original byte at `addr` in the running inferior, unless another enabled
+    /// breakpoint, or a watchpoint waiting for its frame to return, still needs the 0xcc there.
+    fn unpatch_brk_point(&mut self, addr: usize) {
+        if !self.is_running() || self.enabled_brk_addrs().contains(&addr)
+            || self.watch_scope_addrs().contains(&addr) {
+            return;
+        }
+        if let Err(err) = self.inferior.as_mut().unwrap()
+            .remove_brk_point(addr, &mut self.brk_point_map) {
+            println!("Couldn't remove breakpoint at {:#x}: {}", addr, err);
+        }
+    }
+
+    /// Handles watch, rwatch and awatch: watches a variable or expression, or a word of memory
+    /// given as `*addr`.
+    fn set_watchpoint(&mut self, kind: WatchKind, arg_opt: Option<String>) {
+        let expr = match arg_opt {
+            Some(expr) => expr,
+            None => {
+                println!("Usage: watch|rwatch|awatch <variable|*address>");
+                return;
+            }
+        };
+        if !self.is_running() {
+            println!("Error watch without running");
+            return;
+        }
+        let (pc, frame_base) = self.current_scope().unwrap_or((0, 0));
+        let inferior = self.inferior.as_ref().unwrap();
+        let evaluator = Evaluator::new(&self.debug_data, inferior, pc, frame_base);
+        // a bare address has no type, so watch the word there
+        let lvalue = if expr.starts_with("*") && eval::parse_number(expr[1..].trim()).is_ok() {
+            let addr = eval::parse_number(expr[1..].trim()).unwrap() as usize;
+            let word = Type::new("long".to_string(), 8, TypeKind::Base(Encoding::Signed));
+            Ok(LValue { dtype: word, addr })
+        } else {
+            evaluator.lvalue(&expr)
+        };
+        let lvalue = match lvalue {
+            Ok(lvalue) => lvalue,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        if lvalue.dtype.size == 0 {
+            println!("Cannot watch {}: it has no size.", expr);
+            return;
+        }
+        let needed = watch_ranges(lvalue.addr, lvalue.dtype.size).len();
+        if self.watch_slots().len() + needed > NUM_HW_WATCHPOINTS {
+            println!("Not enough hardware debug registers to watch {}.", expr);
+            return;
+        }
+        let old_value = evaluator.read(&lvalue).ok();
+        // frames are ordered innermost first, so the first one ending above the address owns it
+        let on_stack = inferior.stack_range()
+            .map_or(false, |(start, end)| lvalue.addr >= start && lvalue.addr < end);
+        let scope = if on_stack {
+            self.frames.iter().position(|frame| lvalue.addr < frame.cfa).map(|index| WatchScope {
+                tid: inferior.current_thread().1,
+                cfa: self.frames[index].cfa,
+                function: self.frames[index].function.clone(),
+                ret_addr: self.frames.get(index + 1).map(|caller| caller.pc),
+            })
+        } else {
+            None
+        };
+        let ret_addr = scope.as_ref().and_then(|scope| scope.ret_addr);
+        let wp = Watchpoint {
+            num: self.next_brk_num,
+            kind,
+            expr,
+            addr: lvalue.addr,
+            dtype: lvalue.dtype,
+            old_value,
+            scope,
+            enabled: true,
+            hit_count: 0,
+        };
+        println!("{}", watchpoint_title(&wp));
+        self.watch_points.push(wp);
+        self.next_brk_num += 1;
+        self.arm_watchpoints();
+        if let Some(ret_addr) = ret_addr {
+            if let Err(err) = self.inferior.as_mut().unwrap()
+                .insert_brk_point(ret_addr, &mut self.brk_point_map) {
+                println!("Couldn't insert breakpoint at {:#x}: {}", ret_addr, err);
+            }
+        }
+    }
+
+    /// Deletes a watchpoint, and the breakpoint waiting for its frame to return.
+    fn delete_watchpoint(&mut self, index: usize) -> Watchpoint {
+        let wp = self.watch_points.remove(index);
+        if let Some(ret_addr) = wp.scope.as_ref().and_then(|scope| scope.ret_addr) {
+            self.unpatch_brk_point(ret_addr);
+        }
+        self.arm_watchpoints();
+        wp
+    }
+
+    fn watch_scope_addrs(&self) -> Vec<usize> {
+        self.watch_points.iter()
+            .filter_map(|wp| wp.scope.as_ref().and_then(|scope| scope.ret_addr))
+            .collect()
+    }
+
+    /// Whether the inferior stopped where a watched local's frame returns to, with that frame
+    /// gone. A recursive call returning there leaves the stack pointer below it.
+    fn left_watch_scope(&self) -> bool {
+        let inferior = self.inferior.as_ref().unwrap();
+        let (addr, regs) = match (inferior.brk_point_hit(), inferior.get_regs()) {
+            (Some(addr), Ok(regs)) => (addr, regs),
+            _ => return false,
+        };
+        let (_, tid) = inferior.current_thread();
+        self.watch_points.iter().filter_map(|wp| wp.scope.as_ref()).any(|scope| {
+            scope.ret_addr == Some(addr) && scope.tid == tid && regs.rsp as usize >= scope.cfa
+        })
+    }
+
+    /// Deletes the watchpoints on locals whose frame has returned, like gdb does.
+    fn delete_out_of_scope_watchpoints(&mut self) {
+        let (_, current_tid) = self.inferior.as_ref().unwrap().current_thread();
+        let mut index = 0;
+        while index < self.watch_points.len() {
+            let in_scope = match &self.watch_points[index].scope {
+                Some(scope) => {
+                    let frames = if scope.tid == current_tid {
+                        Ok(self.frames.clone())
+                    } else {
+                        self.inferior.as_ref().unwrap()
+                            .thread_backtrace(scope.tid, &self.debug_data)
+                    };
+                    frames.map_or(false, |frames| frames.iter()
+                        .any(|frame| frame.cfa == scope.cfa && frame.function == scope.function))
+                }
+                None => true,
+            };
+            if in_scope {
+                index += 1;
+                continue;
+            }
+            let wp = self.delete_watchpoint(index);
+            println!();
+            println!("Watchpoint {} deleted because the program has left the block in", wp.num);
+            println!("which its expression is valid.");
+        }
+    }
+
+    /// The debug register ranges of the enabled watchpoints, in slot order, each with the index
+    /// of the watchpoint it belongs to.
+    fn watch_slots(&self) -> Vec<(usize, (usize, usize, WatchAccess))> {
+        let mut slots = Vec::new();
+        for (index, wp) in self.watch_points.iter().enumerate().filter(|(_, wp)| wp.enabled) {
+            let access = match wp.kind {
+                WatchKind::Write => WatchAccess::Write,
+                WatchKind::Read | WatchKind::Access => WatchAccess::ReadWrite,
+            };
+            for (addr, len) in watch_ranges(wp.addr, wp.dtype.size) {
+                slots.push((index, (addr, len, access)));
+            }
+        }
+        slots
+    }
+
+    /// Loads the enabled watchpoints into the inferior's debug registers.
+    fn arm_watchpoints(&mut self) {
+        if !self.is_running() {
+            return;
+        }
+        let ranges: Vec<_> = self.watch_slots().into_iter().map(|(_, range)| range).collect();
+        if let Err(err) = self.inferior.as_mut().unwrap().set_hw_watchpoints(&ranges) {
+            println!("Couldn't set hardware watchpoints: {}", err);
+        }
+    }
+
+    /// Index of the watchpoint the inferior stopped on, if any.
+    fn hit_watchpoint(&self) -> Option<usize> {
+        let slot = self.inferior.as_ref()?.watch_hit()?;
+        self.watch_slots().get(slot).map(|(index, _)| *index)
+    }
+
+    fn read_watched(&self, index: usize) -> Option<Vec<u8>> {
+        let wp = &self.watch_points[index];
+        self.inferior.as_ref()?.read_memory(wp.addr, wp.dtype.size).ok()
+    }
+
+    /// Prints the old and new value of a watchpoint that triggered and remembers the new one.
+    fn report_watchpoint(&mut self, index: usize) {
+        let new_value = self.read_watched(index);
+        let wp = &self.watch_points[index];
+        let evaluator = Evaluator::new(&self.debug_data, self.inferior.as_ref().unwrap(), 0, 0);
+        let format = |value: &Option<Vec<u8>>| match value {
+            Some(bytes) => evaluator.format(&wp.dtype, bytes),
+            None => format!("<cannot access memory at {:#x}>", wp.addr),
+        };
+        println!();
+        println!("{}", watchpoint_title(wp));
+        println!();
+        if wp.kind == WatchKind::Read || new_value == wp.old_value {
+            println!("Value = {}", format(&new_value));
+        } else {
+            println!("Old value = {}", format(&wp.old_value));
+            println!("New value = {}", format(&new_value));
+        }
+        let wp = &mut self.watch_points[index];
+        wp.old_value = new_value;
+        wp.hit_count += 1;
+    }
+
     fn parse_address(addr: &str) -> Option<usize> {
         let addr_without_0x = if addr.to_lowercase().starts_with("0x") {
             &addr[2..]
@@ -171,7 +1665,8 @@ impl Debugger {
                         println!("Stopped at  {}", val);
                     },
                     None => {
-                        return;
+                        println!("Stopped at  {} (no line info)",
+                                 self.debug_data.get_label_for_addr(rip));
                     }
                 }
             }
@@ -219,3 +1714,91 @@ impl Debugger {
         }
     }
 }
+
+/// Splits `file:line` or `file:function` at the colon. The `::` of a path like `Circle::new`
+/// doesn't count.
+fn split_location(location: &str) -> (Option<&str>, &str) {
+    let bytes = location.as_bytes();
+    let colon = (0..bytes.len()).rev().find(|&i| {
+        bytes[i] == b':' && (i == 0 || bytes[i - 1] != b':')
+            && bytes.get(i + 1) != Some(&b':')
+    });
+    match colon {
+        Some(pos) => (Some(&location[..pos]), &location[pos + 1..]),
+        None => (None, location),
+    }
+}
+
+/// Says so when a program comes without DWARF, so only its symbol table can be used.
+fn note_missing_debug_info(debug_data: &DwarfData, target: &str) {
+    if !debug_data.has_debug_info() {
+        println!("(No debugging symbols found in {})", target);
+    }
+}
+
+fn watchpoint_title(wp: &Watchpoint) -> String {
+    let kind = match wp.kind {
+        WatchKind::Write => "Hardware watchpoint",
+        WatchKind::Read => "Hardware read watchpoint",
+        WatchKind::Access => "Hardware access (read/write) watchpoint",
+    };
+    format!("{} {}: {}", kind, wp.num, wp.expr)
+}
+
+/// Splits `len` bytes at `addr` into the aligned 1, 2, 4 and 8 byte ranges that debug registers
+/// can watch.
+fn watch_ranges(mut addr: usize, len: usize) -> Vec<(usize, usize)> {
+    let end = addr + len;
+    let mut ranges = Vec::new();
+    while addr < end {
+        let mut size = 8;
+        while addr % size != 0 || addr + size > end {
+            size /= 2;
+        }
+        ranges.push((addr, size));
+        addr += size;
+    }
+    ranges
+}
+
+/// The check behind `Debugger::brk_point_should_stop`, for the places where the inferior is
+/// borrowed while it runs, like `next` going over a call.
+fn brk_points_should_stop(break_points: &mut [Breakpoint], debug_data: &DwarfData,
+                          inferior: &Inferior) -> bool {
+    let addr = match inferior.brk_point_hit() {
+        Some(addr) => addr,
+        None => return true,
+    };
+    let mut should_stop = false;
+    for index in 0..break_points.len() {
+        let bp = &break_points[index];
+        if !bp.enabled || bp.addr != addr {
+            continue;
+        }
+        if let Some(condition) = &bp.condition {
+            // conditions are evaluated in the innermost frame, which is where we stopped
+            let frame_base = inferior.backtrace(debug_data).ok()
+                .and_then(|frames| frames.first().map(|frame| frame.cfa))
+                .unwrap_or(0);
+            let evaluator = Evaluator::new(debug_data, inferior, addr, frame_base);
+            match evaluator.integer_value(condition) {
+                Ok(0) => continue,
+                Ok(_) => {}
+                Err(err) => {
+                    println!("Error in testing condition for breakpoint {}:", bp.num);
+                    println!("{}", err);
+                    should_stop = true;
+                    continue;
+                }
+            }
+        }
+        let bp = &mut break_points[index];
+        bp.hit_count += 1;
+        if bp.ignore_count > 0 {
+            bp.ignore_count -= 1;
+        } else {
+            should_stop = true;
+        }
+    }
+    should_stop
+}
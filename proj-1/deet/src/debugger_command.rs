@@ -1,9 +1,41 @@
 pub enum DebuggerCommand {
     Quit,
     Run(Vec<String>),
+    Attach(Option<String>),
+    Detach,
     Continue,
+    Next,
+    Step,
+    Finish,
     Backtrace,
-    BreakPoint(Option<String>)
+    /// Location and an optional condition
+    BreakPoint(Option<String>, Option<String>),
+    /// Breakpoint number and how many hits to ignore
+    Ignore(Option<String>, Option<String>),
+    Frame(Option<String>),
+    Up(Option<String>),
+    Down(Option<String>),
+    Print(Option<String>),
+    List(Option<String>),
+    /// What follows "x/" and the address expression
+    Examine(String, Option<String>),
+    Watch(Option<String>),
+    RWatch(Option<String>),
+    AWatch(Option<String>),
+    InfoBreakpoints,
+    InfoArgs,
+    InfoLocals,
+    InfoRegisters(Vec<String>),
+    InfoThreads,
+    InfoSignals(Vec<String>),
+    InfoSymbol(Option<String>),
+    Handle(Vec<String>),
+    Thread(Option<String>),
+    /// Everything after "set"
+    Set(Option<String>),
+    Delete(Vec<String>),
+    Disable(Vec<String>),
+    Enable(Vec<String>),
 }
 
 impl DebuggerCommand {
@@ -16,15 +48,103 @@ impl DebuggerCommand {
                     args.iter().map(|s| s.to_string()).collect(),
                 ))
             },
+            "attach" => Some(DebuggerCommand::Attach(tokens.get(1).map(|s| s.to_string()))),
+            "detach" => Some(DebuggerCommand::Detach),
             "c" | "cont" | "continue" => Some(DebuggerCommand::Continue),
+            "n" | "next" => Some(DebuggerCommand::Next),
+            "s" | "step" => Some(DebuggerCommand::Step),
+            "fin" | "finish" => Some(DebuggerCommand::Finish),
             "bt" | "back" | "backtrace" => Some(DebuggerCommand::Backtrace),
             "b" | "break" | "breakpoint" =>{
                 let mut  arg_1 : Option<String> = None;
                 if tokens.len() > 1 {
                     arg_1 = Some(tokens[1].to_string());
                 }
-                Some(DebuggerCommand::BreakPoint(arg_1))
+                let condition = match tokens.get(2) {
+                    None => None,
+                    Some(&"if") => Some(tokens[3..].join(" ")),
+                    // `if(x > 1)`, with no space before the parenthesis
+                    Some(token) if token.starts_with("if(") => {
+                        Some(tokens[2..].join(" ")[2..].to_string())
+                    }
+                    // anything else would be dropped, setting a breakpoint without the condition
+                    Some(_) => return Some(DebuggerCommand::BreakPoint(None, None)),
+                };
+                Some(DebuggerCommand::BreakPoint(arg_1, condition))
             },
+            "ignore" => Some(DebuggerCommand::Ignore(
+                tokens.get(1).map(|s| s.to_string()),
+                tokens.get(2).map(|s| s.to_string()),
+            )),
+            "f" | "frame" => Some(DebuggerCommand::Frame(tokens.get(1).map(|s| s.to_string()))),
+            "up" => Some(DebuggerCommand::Up(tokens.get(1).map(|s| s.to_string()))),
+            "down" => Some(DebuggerCommand::Down(tokens.get(1).map(|s| s.to_string()))),
+            "l" | "list" => Some(DebuggerCommand::List(tokens.get(1).map(|s| s.to_string()))),
+            "p" | "print" => {
+                let mut arg_1 : Option<String> = None;
+                if tokens.len() > 1 {
+                    arg_1 = Some(tokens[1..].join(" "));
+                }
+                Some(DebuggerCommand::Print(arg_1))
+            },
+            cmd if cmd == "x" || cmd.starts_with("x/") => {
+                let mut arg_1 : Option<String> = None;
+                if tokens.len() > 1 {
+                    arg_1 = Some(tokens[1..].join(" "));
+                }
+                Some(DebuggerCommand::Examine(cmd[1..].trim_start_matches('/').to_string(), arg_1))
+            },
+            "set" => {
+                let mut arg_1 : Option<String> = None;
+                if tokens.len() > 1 {
+                    arg_1 = Some(tokens[1..].join(" "));
+                }
+                Some(DebuggerCommand::Set(arg_1))
+            },
+            "watch" | "rwatch" | "awatch" => {
+                let mut arg_1 : Option<String> = None;
+                if tokens.len() > 1 {
+                    arg_1 = Some(tokens[1..].join(" "));
+                }
+                match tokens[0] {
+                    "watch" => Some(DebuggerCommand::Watch(arg_1)),
+                    "rwatch" => Some(DebuggerCommand::RWatch(arg_1)),
+                    _ => Some(DebuggerCommand::AWatch(arg_1)),
+                }
+            },
+            "i" | "info" => {
+                match tokens.get(1) {
+                    Some(&"b") | Some(&"break") | Some(&"breakpoints") =>
+                        Some(DebuggerCommand::InfoBreakpoints),
+                    Some(&"args") => Some(DebuggerCommand::InfoArgs),
+                    Some(&"locals") => Some(DebuggerCommand::InfoLocals),
+                    Some(&"r") | Some(&"registers") => Some(DebuggerCommand::InfoRegisters(
+                        tokens[2..].iter().map(|s| s.to_string()).collect(),
+                    )),
+                    Some(&"threads") => Some(DebuggerCommand::InfoThreads),
+                    Some(&"signals") | Some(&"handle") => Some(DebuggerCommand::InfoSignals(
+                        tokens[2..].iter().map(|s| s.to_string()).collect(),
+                    )),
+                    Some(&"symbol") if tokens.len() > 2 => {
+                        Some(DebuggerCommand::InfoSymbol(Some(tokens[2..].join(" "))))
+                    }
+                    Some(&"symbol") => Some(DebuggerCommand::InfoSymbol(None)),
+                    _ => None,
+                }
+            },
+            "handle" => Some(DebuggerCommand::Handle(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "t" | "thread" => Some(DebuggerCommand::Thread(tokens.get(1).map(|s| s.to_string()))),
+            "d" | "delete" => Some(DebuggerCommand::Delete(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "disable" => Some(DebuggerCommand::Disable(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "enable" => Some(DebuggerCommand::Enable(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
             // Default case:
             _ => None,
         }
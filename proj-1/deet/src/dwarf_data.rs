@@ -1,7 +1,11 @@
 use crate::gimli_wrapper;
+use crate::unwind::{self, CallFrameInfo};
 use addr2line::Context;
-use object::Object;
+use object::{Object, ObjectSection, ObjectSegment, SymbolKind};
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::convert::TryInto;
+use std::path::{Path, PathBuf};
 use std::{fmt, fs};
 
 #[derive(Debug)]
@@ -10,11 +14,41 @@ pub enum Error {
     DwarfFormatError(gimli_wrapper::Error),
 }
 
+/// Debugging information of the program and the shared libraries mapped into it. Addresses are
+/// where the code is loaded in the inferior once it runs; until then they are the ones in the
+/// program's file.
 pub struct DwarfData {
     files: Vec<File>,
-    addr2line: Context<addr2line::gimli::EndianRcSlice<addr2line::gimli::RunTimeEndian>>,
+    types: HashMap<usize, Type>,
+    /// The program first, then the shared libraries
+    objects: Vec<LoadedObject>,
 }
 
+/// An object file mapped into the inferior. Its line table and call frame information are looked
+/// up with addresses in the file, so its load bias is taken off first.
+struct LoadedObject {
+    path: String,
+    /// How far from its addresses in the file the object was loaded
+    bias: usize,
+    /// Addresses spanned by its segments, as loaded
+    start: usize,
+    end: usize,
+    /// Index of its first compilation unit in `DwarfData::files`
+    first_file: usize,
+    /// None if its DWARF is missing or malformed
+    addr2line: Option<Context<addr2line::gimli::EndianRcSlice<addr2line::gimli::RunTimeEndian>>>,
+    call_frame_info: CallFrameInfo,
+    /// The ELF symbol table, sorted by address
+    symbols: Vec<Symbol>,
+}
+
+/// Type offsets of the n-th object are moved up by n times this, so that they don't collide.
+const TYPE_OFFSET_STRIDE: usize = 1 << 32;
+
+/// How many types deep we follow references between types, so that malformed debugging
+/// information with a cycle in it can't send us round forever.
+pub const MAX_TYPE_DEPTH: usize = 32;
+
 impl fmt::Debug for DwarfData {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "DwarfData {{files: {:?}}}", self.files)
@@ -29,86 +63,400 @@ impl From<gimli_wrapper::Error> for Error {
 
 impl DwarfData {
     pub fn from_file(path: &str) -> Result<DwarfData, Error> {
-        let file = fs::File::open(path).or(Err(Error::ErrorOpeningFile))?;
-        let mmap = unsafe { memmap::Mmap::map(&file).or(Err(Error::ErrorOpeningFile))? };
-        let object = object::File::parse(&*mmap)
-            .or_else(|e| Err(gimli_wrapper::Error::ObjectError(e.to_string())))?;
-        let endian = if object.is_little_endian() {
-            gimli::RunTimeEndian::Little
-        } else {
-            gimli::RunTimeEndian::Big
-        };
+        let (object, files, types) = load_object(path, 0, 0)?;
         Ok(DwarfData {
-            files: gimli_wrapper::load_file(&object, endian)?,
-            addr2line: Context::new(&object).or_else(|e| Err(gimli_wrapper::Error::from(e)))?,
+            files,
+            types,
+            objects: vec![object],
         })
     }
 
+    /// No debugging information at all, for a program `path` that couldn't be read. Nothing is
+    /// found in it, but shared libraries can still be loaded alongside.
+    pub fn empty(path: &str) -> DwarfData {
+        DwarfData {
+            files: Vec::new(),
+            types: HashMap::new(),
+            objects: vec![LoadedObject {
+                path: path.to_string(),
+                bias: 0,
+                start: 0,
+                end: 0,
+                first_file: 0,
+                addr2line: None,
+                call_frame_info: CallFrameInfo::default(),
+                symbols: Vec::new(),
+            }],
+        }
+    }
+
+    /// Moves the program's addresses to where it is mapped, `load_address` being the start of the
+    /// mapping of its first page.
+    pub fn relocate(&mut self, load_address: usize) {
+        let end = self.objects.get(1).map_or(self.files.len(), |object| object.first_file);
+        let program = &mut self.objects[0];
+        let delta = load_address.wrapping_sub(program.start);
+        program.bias = program.bias.wrapping_add(delta);
+        program.start = program.start.wrapping_add(delta);
+        program.end = program.end.wrapping_add(delta);
+        relocate_symbols(&mut program.symbols, delta);
+        for file in &mut self.files[..end] {
+            relocate_file(file, delta);
+        }
+    }
+
+    /// Loads the debugging information of the shared library at `path`, mapped at
+    /// `load_address`.
+    pub fn load_library(&mut self, path: &str, load_address: usize) -> Result<(), Error> {
+        let type_base = self.objects.len() * TYPE_OFFSET_STRIDE;
+        let (mut object, mut files, types) =
+            load_object(path, type_base, self.files.len())?;
+        // load_object leaves the addresses as they are in the file
+        let delta = load_address.wrapping_sub(object.start);
+        object.bias = delta;
+        object.start = load_address;
+        object.end = object.end.wrapping_add(delta);
+        relocate_symbols(&mut object.symbols, delta);
+        for file in &mut files {
+            relocate_file(file, delta);
+        }
+        self.files.append(&mut files);
+        self.types.extend(types);
+        self.objects.push(object);
+        Ok(())
+    }
+
+    /// Forgets the shared libraries, e.g. before the program is run again.
+    pub fn unload_libraries(&mut self) {
+        if let Some(library) = self.objects.get(1) {
+            self.files.truncate(library.first_file);
+            self.types.retain(|offset, _| *offset < TYPE_OFFSET_STRIDE);
+            self.objects.truncate(1);
+        }
+    }
+
+    /// Whether the program has DWARF debugging information, rather than just a symbol table.
+    pub fn has_debug_info(&self) -> bool {
+        let end = self.objects.get(1).map_or(self.files.len(), |object| object.first_file);
+        end > 0
+    }
+
+    /// The ELF symbol containing `addr`, the offset into it, and the path of the shared library
+    /// it is in, or None if it is in the program.
+    pub fn get_elf_symbol_for_addr(&self, addr: usize) -> Option<(&Symbol, usize, Option<&str>)> {
+        let index = self
+            .objects
+            .iter()
+            .position(|object| addr >= object.start && addr < object.end)?;
+        let object = &self.objects[index];
+        // of the aliases an address often has, prefer the public name, e.g. printf to _IO_printf
+        let symbol = object
+            .symbols
+            .iter()
+            .filter(|symbol| addr >= symbol.address && addr < symbol.address + symbol.size.max(1))
+            .min_by_key(|symbol| symbol.name.starts_with('_'))?;
+        let library = if index == 0 { None } else { Some(object.path.as_str()) };
+        Some((symbol, addr - symbol.address, library))
+    }
+
+    /// The address of the ELF symbol `name`, in the program or any library.
+    pub fn get_addr_for_symbol(&self, name: &str) -> Option<usize> {
+        self.objects
+            .iter()
+            .flat_map(|object| object.symbols.iter())
+            .find(|symbol| symbol.name == name)
+            .map(|symbol| symbol.address)
+    }
+
+    /// The object file whose segments contain `addr`.
+    fn object_for_addr(&self, addr: usize) -> Option<&LoadedObject> {
+        self.objects.iter().find(|object| addr >= object.start && addr < object.end)
+    }
+
     #[allow(dead_code)]
     fn get_target_file(&self, file: &str) -> Option<&File> {
         self.files.iter().find(|f| {
-            f.name == file || (!file.contains("/") && f.name.ends_with(&format!("/{}", file)))
+            f.name == file
+                || Path::new(&f.comp_dir).join(&f.name) == Path::new(file)
+                || (!file.contains("/") && f.name.ends_with(&format!("/{}", file)))
         })
     }
 
-    #[allow(dead_code)]
+    /// Where the source of `file`, a compilation unit name or a path from the line table, is on
+    /// disk.
+    pub fn get_source_path(&self, file: &str) -> Option<PathBuf> {
+        if Path::new(file).is_absolute() {
+            return Some(PathBuf::from(file));
+        }
+        if let Some(target_file) = self.get_target_file(file) {
+            return Some(Path::new(&target_file.comp_dir).join(&target_file.name));
+        }
+        // a file that isn't a compilation unit of its own, like a header
+        let suffix = format!("/{}", file);
+        self.files
+            .iter()
+            .flat_map(|f| f.lines.iter())
+            .find(|line| line.file.ends_with(&suffix))
+            .map(|line| PathBuf::from(&line.file))
+    }
+
+    /// Returns the lowest address generated for `line_number`, or None if that line has no
+    /// code.
     pub fn get_addr_for_line(&self, file: Option<&str>, line_number: usize) -> Option<usize> {
-        let target_file = match file {
-            Some(filename) => self.get_target_file(filename)?,
-            None => self.files.get(0)?,
+        let wanted = match file {
+            Some(filename) => self.get_source_path(filename)?,
+            None => self.get_source_path(&self.files.get(0)?.name)?,
         };
-        Some(
-            target_file
-                .lines
-                .iter()
-                .find(|line| line.number >= line_number)?
-                .address,
-        )
+        let wanted = wanted.as_path();
+        self.files
+            .iter()
+            .flat_map(|f| {
+                let own_path = Path::new(&f.comp_dir).join(&f.name);
+                f.lines
+                    .iter()
+                    .filter(move |line| {
+                        line.number == line_number
+                            && if line.file == f.name {
+                                own_path.as_path() == wanted
+                            } else {
+                                Path::new(&line.file) == wanted
+                            }
+                    })
+                    .map(|line| line.address)
+            })
+            .min()
     }
 
     #[allow(dead_code)]
     pub fn get_addr_for_function(&self, file: Option<&str>, func_name: &str) -> Option<usize> {
+        // declarations of functions defined elsewhere, e.g. in a shared library, have no code
+        let is_target = |func: &&Function| func.name == func_name && func.text_length > 0;
         match file {
             Some(filename) => Some(
                 self.get_target_file(filename)?
                     .functions
                     .iter()
-                    .find(|func| func.name == func_name)?
+                    .find(is_target)?
                     .address,
             ),
             None => {
                 for file in &self.files {
-                    if let Some(func) = file.functions.iter().find(|func| func.name == func_name) {
+                    if let Some(func) = file.functions.iter().find(is_target) {
                         return Some(func.address);
                     }
                 }
-                None
+                // code built without -g still has a symbol table
+                self.objects
+                    .iter()
+                    .flat_map(|object| object.symbols.iter())
+                    .find(|symbol| symbol.is_function && symbol.name == func_name)
+                    .map(|symbol| symbol.address)
             }
         }
     }
 
     #[allow(dead_code)]
     pub fn get_line_from_addr(&self, curr_addr: usize) -> Option<Line> {
-        let location = self
+        let object = self.object_for_addr(curr_addr)?;
+        let location = object
             .addr2line
-            .find_location(curr_addr.try_into().unwrap())
+            .as_ref()?
+            .find_location((curr_addr - object.bias).try_into().unwrap())
             .ok()??;
         Some(Line {
             file: location.file?.to_string(),
             number: location.line?.try_into().unwrap(),
             address: curr_addr,
+            prologue_end: false,
         })
     }
 
     #[allow(dead_code)]
     pub fn get_function_from_addr(&self, curr_addr: usize) -> Option<String> {
-        let frame = self
-            .addr2line
-            .find_frames(curr_addr.try_into().unwrap())
-            .ok()?
-            .next()
-            .ok()??;
-        Some(frame.function?.raw_name().ok()?.to_string())
+        let object = self.object_for_addr(curr_addr)?;
+        let frame = object.addr2line.as_ref().and_then(|addr2line| {
+            addr2line
+                .find_frames((curr_addr - object.bias).try_into().unwrap())
+                .ok()?
+                .next()
+                .ok()?
+        });
+        match frame.and_then(|frame| frame.function) {
+            Some(function) => Some(function.demangle().ok()?.to_string()),
+            None => self
+                .get_elf_symbol_for_addr(curr_addr)
+                .filter(|(symbol, _, _)| symbol.is_function)
+                .map(|(symbol, _, _)| symbol.name.clone()),
+        }
+    }
+
+    /// The functions whose path ends with the one given, e.g. `Circle::new` for
+    /// `shapes::Circle::new`, with their demangled names. If some match the whole path, only
+    /// those are returned.
+    pub fn find_functions(&self, path: &str) -> Vec<(String, usize)> {
+        let wanted = path_components(path);
+        if wanted.is_empty() {
+            return Vec::new();
+        }
+        let functions = self
+            .files
+            .iter()
+            .flat_map(|file| file.functions.iter())
+            .filter(|func| func.text_length > 0)
+            .filter_map(|func| Some((self.get_function_from_addr(func.address)?, func.address)));
+        let symbols = self
+            .objects
+            .iter()
+            .flat_map(|object| object.symbols.iter())
+            .filter(|symbol| symbol.is_function)
+            .map(|symbol| (symbol.name.clone(), symbol.address));
+        let mut matches: Vec<(String, usize, bool)> = Vec::new();
+        for (name, address) in functions.chain(symbols) {
+            if matches.iter().any(|(_, known, _)| *known == address) {
+                continue;
+            }
+            let components = path_components(&name);
+            if components.ends_with(&wanted) {
+                matches.push((name, address, components.len() == wanted.len()));
+            }
+        }
+        if matches.iter().any(|(_, _, exact)| *exact) {
+            matches.retain(|(_, _, exact)| *exact);
+        }
+        matches.into_iter().map(|(name, address, _)| (name, address)).collect()
+    }
+
+    /// Returns the function whose code contains `curr_addr`.
+    pub fn get_function_for_addr(&self, curr_addr: usize) -> Option<&Function> {
+        self.files
+            .iter()
+            .flat_map(|file| file.functions.iter())
+            .find(|func| func.contains(curr_addr))
+    }
+
+    /// Where a breakpoint on the function starting at `addr` goes: past its prologue, so that its
+    /// frame is set up by then. That is the row marked as the end of the prologue, or else the
+    /// function's second row in the line table. Anything else is left as it is.
+    pub fn skip_prologue(&self, addr: usize) -> usize {
+        let func = match self
+            .files
+            .iter()
+            .flat_map(|file| file.functions.iter())
+            .find(|func| func.address == addr && func.text_length > 0)
+        {
+            Some(func) => func,
+            None => return addr,
+        };
+        let rows: Vec<&Line> = self
+            .files
+            .iter()
+            .flat_map(|file| file.lines.iter())
+            .filter(|line| func.contains(line.address))
+            .collect();
+        rows.iter()
+            .filter(|line| line.prologue_end)
+            .map(|line| line.address)
+            .min()
+            .or_else(|| {
+                rows.iter()
+                    .map(|line| line.address)
+                    .filter(|row_addr| *row_addr > addr)
+                    .min()
+            })
+            .unwrap_or(addr)
+    }
+
+    /// Returns the line table row that starts exactly at `addr`, if any.
+    pub fn get_line_at_addr(&self, addr: usize) -> Option<&Line> {
+        self.files
+            .iter()
+            .flat_map(|file| file.lines.iter())
+            .find(|line| line.address == addr)
+    }
+
+    /// Looks up a variable visible from `curr_addr`: locals and arguments of the function
+    /// containing it first, then globals of its file, then globals of any file.
+    pub fn get_variable(&self, curr_addr: usize, name: &str) -> Option<&Variable> {
+        if let Some(func) = self.get_function_for_addr(curr_addr) {
+            let mut vars = func.variables.iter().chain(func.args.iter());
+            if let Some(var) = vars.find(|var| var.name == name) {
+                return Some(var);
+            }
+        }
+        let curr_file = self
+            .files
+            .iter()
+            .find(|file| file.functions.iter().any(|func| func.contains(curr_addr)));
+        if let Some(file) = curr_file {
+            if let Some(var) = file.global_variables.iter().find(|var| var.name == name) {
+                return Some(var);
+            }
+        }
+        self.files
+            .iter()
+            .flat_map(|file| file.global_variables.iter())
+            .find(|var| var.name == name)
+    }
+
+    /// Returns the function or global variable containing `addr`, and the offset into it. The
+    /// ELF symbol table is used for code without debugging information.
+    pub fn get_symbol_for_addr(&self, addr: usize) -> Option<(&str, usize)> {
+        if let Some(func) = self.get_function_for_addr(addr) {
+            return Some((&func.name, addr - func.address));
+        }
+        self.files
+            .iter()
+            .flat_map(|file| file.global_variables.iter())
+            .find_map(|var| match var.location {
+                Location::Address(start) if addr >= start && addr < start + var.entity_type.size => {
+                    Some((var.name.as_str(), addr - start))
+                }
+                _ => None,
+            })
+            .or_else(|| {
+                self.get_elf_symbol_for_addr(addr)
+                    .map(|(symbol, offset, _)| (symbol.name.as_str(), offset))
+            })
+    }
+
+    /// The address, followed by the function or variable it is in, e.g. `0x401136 <main+16>`.
+    pub fn get_label_for_addr(&self, addr: usize) -> String {
+        match self.get_symbol_for_addr(addr) {
+            Some((name, 0)) => format!("{:#x} <{}>", addr, name),
+            Some((name, offset)) => format!("{:#x} <{}+{}>", addr, name, offset),
+            None => format!("{:#x}", addr),
+        }
+    }
+
+    pub fn get_type(&self, offset: usize) -> Option<&Type> {
+        self.types.get(&offset)
+    }
+
+    /// Follows typedefs and const/volatile qualifiers down to the underlying type.
+    pub fn strip_typedefs<'a>(&'a self, mut dtype: &'a Type) -> &'a Type {
+        for _ in 0..MAX_TYPE_DEPTH {
+            match dtype.kind {
+                TypeKind::Typedef(Some(offset)) | TypeKind::Qualified(Some(offset)) => {
+                    match self.get_type(offset) {
+                        Some(inner) => dtype = inner,
+                        None => break,
+                    }
+                }
+                _ => break,
+            }
+        }
+        dtype
+    }
+
+    /// Unwinds one frame with the call frame information; see `CallFrameInfo::unwind`.
+    pub fn unwind(
+        &self,
+        lookup_pc: usize,
+        regs: &unwind::Registers,
+        read: &dyn Fn(usize) -> Option<usize>,
+    ) -> Option<(usize, unwind::Registers)> {
+        let object = self.object_for_addr(lookup_pc)?;
+        object.call_frame_info.unwind(lookup_pc - object.bias, regs, read)
     }
 
     #[allow(dead_code)]
@@ -132,6 +480,12 @@ impl DwarfData {
                     "  * {} (declared on line {}, located at {:#x}, {} bytes long)",
                     func.name, func.line_number, func.address, func.text_length
                 );
+                for var in &func.args {
+                    println!(
+                        "    * Argument: {} ({}, located at {}, declared at line {})",
+                        var.name, var.entity_type.name, var.location, var.line_number
+                    );
+                }
                 for var in &func.variables {
                     println!(
                         "    * Variable: {} ({}, located at {}, declared at line {})",
@@ -148,21 +502,280 @@ impl DwarfData {
     }
 }
 
-#[derive(Debug, Clone, Default)]
+/// Parses the object file at `path`. Its addresses are left as they are in the file, and its type
+/// offsets are moved up by `type_base`.
+fn load_object(
+    path: &str,
+    type_base: usize,
+    first_file: usize,
+) -> Result<(LoadedObject, Vec<File>, HashMap<usize, Type>), Error> {
+    let file = fs::File::open(path).or(Err(Error::ErrorOpeningFile))?;
+    let mmap = unsafe { memmap::Mmap::map(&file).or(Err(Error::ErrorOpeningFile))? };
+    let object = object::File::parse(&*mmap)
+        .or_else(|e| Err(gimli_wrapper::Error::ObjectError(e.to_string())))?;
+    let endian = if object.is_little_endian() {
+        gimli::RunTimeEndian::Little
+    } else {
+        gimli::RunTimeEndian::Big
+    };
+    // without usable DWARF, we make do with the symbol table
+    let (mut files, types) = gimli_wrapper::load_file(&object, endian).unwrap_or_default();
+    // the first page is mapped at a page boundary, even if the first segment doesn't start at one
+    let start = object.segments().map(|segment| segment.address()).min().unwrap_or(0) as usize
+        & !(PAGE_SIZE - 1);
+    let end = object
+        .segments()
+        .map(|segment| segment.address() + segment.size())
+        .max()
+        .unwrap_or(0) as usize;
+    let mut types: HashMap<usize, Type> = types
+        .into_iter()
+        .map(|(offset, dtype)| (offset + type_base, dtype))
+        .collect();
+    if type_base != 0 {
+        for dtype in types.values_mut() {
+            rebase_type(dtype, type_base);
+        }
+        for file in &mut files {
+            let locals = file
+                .functions
+                .iter_mut()
+                .flat_map(|func| func.args.iter_mut().chain(func.variables.iter_mut()));
+            for var in file.global_variables.iter_mut().chain(locals) {
+                rebase_type(&mut var.entity_type, type_base);
+            }
+        }
+    }
+    Ok((
+        LoadedObject {
+            path: path.to_string(),
+            bias: 0,
+            start,
+            end,
+            first_file,
+            addr2line: Context::new(&object).ok(),
+            call_frame_info: CallFrameInfo::load(&object),
+            symbols: load_symbols(&object),
+        },
+        files,
+        types,
+    ))
+}
+
+const PAGE_SIZE: usize = 4096;
+
+/// The functions and data objects in `object`'s .symtab and .dynsym, sorted by address.
+fn load_symbols(object: &object::File) -> Vec<Symbol> {
+    let mut symbols: Vec<Symbol> = Vec::new();
+    for (_, symbol) in object.symbols().chain(object.dynamic_symbols()) {
+        let is_function = match symbol.kind() {
+            SymbolKind::Text => true,
+            SymbolKind::Data => false,
+            _ => continue,
+        };
+        let (name, index) = match (symbol.name(), symbol.section_index()) {
+            (Some(name), Some(index)) if !name.is_empty() => (name, index),
+            _ => continue,
+        };
+        let section = object
+            .section_by_index(index)
+            .ok()
+            .and_then(|section| section.name().ok().map(|name| name.to_string()))
+            .unwrap_or_default();
+        symbols.push(Symbol {
+            name: addr2line::demangle_auto(Cow::from(name), None).into_owned(),
+            address: symbol.address() as usize,
+            size: symbol.size() as usize,
+            section,
+            is_function,
+        });
+    }
+    symbols.sort_by(|a, b| (a.address, &a.name).cmp(&(b.address, &b.name)));
+    // the two tables share most of their entries
+    symbols.dedup_by(|a, b| a.address == b.address && a.name == b.name);
+    symbols
+}
+
+fn relocate_symbols(symbols: &mut Vec<Symbol>, delta: usize) {
+    for symbol in symbols {
+        symbol.address = symbol.address.wrapping_add(delta);
+    }
+}
+
+/// Splits a demangled function name into the parts of its path, leaving out template and generic
+/// arguments and parameter lists, so that `<shapes::Circle as Shape>::area`,
+/// `shapes::Circle::area` and `geo::Point::sum() const` can be compared part by part.
+fn path_components(name: &str) -> Vec<String> {
+    let mut components = Vec::new();
+    for part in split_top_level(strip_return_type(name), "::") {
+        let part = part.trim();
+        if part.starts_with('<') && part.ends_with('>') {
+            // a Rust impl path: only the type counts, not the trait
+            let inner = &part[1..part.len() - 1];
+            let self_type = split_top_level(inner, " as ").into_iter().next().unwrap_or(inner);
+            components.extend(path_components(self_type));
+        } else if let Some(end) = find_top_level(part, &['<', '(']) {
+            // template arguments and parameters aren't part of the name
+            components.push(part[..end].to_string());
+            if part.as_bytes()[end] == b'(' {
+                break;
+            }
+        } else if !part.is_empty() {
+            components.push(part.to_string());
+        }
+    }
+    components
+}
+
+/// Drops the return type that demangled template functions start with, as in
+/// `int twice<int>(int)`.
+fn strip_return_type(name: &str) -> &str {
+    let bytes = name.as_bytes();
+    let mut depth = 0;
+    let mut start = 0;
+    for (index, byte) in bytes.iter().enumerate() {
+        match byte {
+            // the parameter list: anything after it is a qualifier
+            b'(' if depth == 0 && index > 0 && bytes[index - 1] != b':' => break,
+            b'<' | b'(' | b'[' => depth += 1,
+            b'>' | b')' | b']' => depth -= 1,
+            b' ' if depth == 0 => start = index + 1,
+            _ => {}
+        }
+    }
+    &name[start..]
+}
+
+/// Splits `text` at each `separator` that isn't inside brackets.
+fn split_top_level<'a>(text: &'a str, separator: &str) -> Vec<&'a str> {
+    let bytes = text.as_bytes();
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'<' | b'(' | b'[' => depth += 1,
+            b'>' | b')' | b']' => depth -= 1,
+            _ if depth == 0 && bytes[index..].starts_with(separator.as_bytes()) => {
+                parts.push(&text[start..index]);
+                index += separator.len();
+                start = index;
+                continue;
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// The index of the first of `brackets` in `text` that isn't at the very start.
+fn find_top_level(text: &str, brackets: &[char]) -> Option<usize> {
+    text.char_indices()
+        .skip(1)
+        .find(|(_, c)| brackets.contains(c))
+        .map(|(index, _)| index)
+}
+
+/// Adds `delta` to every address in `file`.
+fn relocate_file(file: &mut File, delta: usize) {
+    let relocate_var = |var: &mut Variable| {
+        if let Location::Address(addr) = var.location {
+            var.location = Location::Address(addr.wrapping_add(delta));
+        }
+    };
+    file.global_variables.iter_mut().for_each(relocate_var);
+    for func in &mut file.functions {
+        func.address = func.address.wrapping_add(delta);
+        // static locals live at fixed addresses too
+        func.args.iter_mut().chain(func.variables.iter_mut()).for_each(relocate_var);
+    }
+    for line in &mut file.lines {
+        line.address = line.address.wrapping_add(delta);
+    }
+}
+
+/// Moves the offsets `dtype` refers to other types by up by `base`.
+fn rebase_type(dtype: &mut Type, base: usize) {
+    match &mut dtype.kind {
+        TypeKind::Pointer(Some(offset))
+        | TypeKind::Typedef(Some(offset))
+        | TypeKind::Qualified(Some(offset))
+        | TypeKind::Array(offset, _) => *offset += base,
+        TypeKind::Struct(members) => {
+            for member in members {
+                member.type_offset += base;
+            }
+        }
+        _ => {}
+    }
+}
+
+/// An entry of an ELF symbol table.
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub address: usize,
+    pub size: usize,
+    /// Name of the section it is in, e.g. ".text"
+    pub section: String,
+    /// Code rather than data
+    pub is_function: bool,
+}
+
+#[derive(Debug, Clone)]
 pub struct Type {
     pub name: String,
     pub size: usize,
+    pub kind: TypeKind,
 }
 
 impl Type {
-    pub fn new(name: String, size: usize) -> Self {
+    pub fn new(name: String, size: usize, kind: TypeKind) -> Self {
         Type {
             name: name,
             size: size,
+            kind: kind,
         }
     }
 }
 
+/// How a base type's bytes should be interpreted (DW_AT_encoding).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Signed,
+    Unsigned,
+    SignedChar,
+    UnsignedChar,
+    Float,
+    Boolean,
+}
+
+/// The shape of a type. Other types are referred to by their offset in .debug_info, which is
+/// the key of `DwarfData::get_type`.
+#[derive(Debug, Clone)]
+pub enum TypeKind {
+    Base(Encoding),
+    /// Offset of the pointee, or None for void *
+    Pointer(Option<usize>),
+    Struct(Vec<Member>),
+    /// Offset of the element type and the length of each dimension
+    Array(usize, Vec<usize>),
+    Typedef(Option<usize>),
+    Enum(Vec<(String, i64)>),
+    /// const and volatile qualifiers
+    Qualified(Option<usize>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub name: String,
+    pub type_offset: usize,
+    pub offset: usize, // Byte offset from the start of the struct
+}
+
 #[derive(Clone)]
 pub enum Location {
     Address(usize),
@@ -199,12 +812,20 @@ pub struct Function {
     pub address: usize,
     pub text_length: usize,
     pub line_number: usize, // Line number in source file
-    pub variables: Vec<Variable>,
+    pub args: Vec<Variable>,      // Formal parameters
+    pub variables: Vec<Variable>, // Local variables
+}
+
+impl Function {
+    pub fn contains(&self, addr: usize) -> bool {
+        addr >= self.address && addr < self.address + self.text_length
+    }
 }
 
 #[derive(Debug, Default, Clone)]
 pub struct File {
     pub name: String,
+    pub comp_dir: String, // What a relative name is relative to
     pub global_variables: Vec<Variable>,
     pub functions: Vec<Function>,
     pub lines: Vec<Line>,
@@ -215,6 +836,7 @@ pub struct Line {
     pub file: String,
     pub number: usize,
     pub address: usize,
+    pub prologue_end: bool, // The compiler says the function's frame is set up here
 }
 
 impl fmt::Display for Line {
@@ -0,0 +1,609 @@
+//! Evaluation of the small C-like expressions understood by `print` and breakpoint conditions:
+//! variable names, `$registers`, `*ptr`, `&var`, `s.field`, `ptr->field` and `arr[index]`,
+//! combined with integer arithmetic, comparisons and `&&`/`||`, against the memory of a stopped
+//! inferior.
+
+use crate::dwarf_data::{DwarfData, Encoding, Location, Type, TypeKind, Variable};
+use crate::inferior::Inferior;
+use crate::registers;
+use std::collections::HashMap;
+
+/// Longest string or array we print before cutting it short with "..."
+pub const MAX_ELEMENTS: usize = 200;
+
+/// A typed location in the inferior's memory.
+pub struct LValue {
+    pub dtype: Type,
+    pub addr: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Register(String),
+    Number(i64),
+    Star,
+    Dot,
+    Arrow,
+    LBracket,
+    RBracket,
+    LParen,
+    RParen,
+    /// Any operator other than `*`, which doubles as dereference
+    Op(String),
+}
+
+enum Expr {
+    Var(String),
+    Register(String),
+    Number(i64),
+    Deref(Box<Expr>),
+    AddressOf(Box<Expr>),
+    Member(Box<Expr>, String),
+    Index(Box<Expr>, Box<Expr>),
+    Unary(String, Box<Expr>),
+    Binary(String, Box<Expr>, Box<Expr>),
+}
+
+/// Binary operators from loosest to tightest binding
+const PRECEDENCE: [&[&str]; 6] = [
+    &["||"],
+    &["&&"],
+    &["==", "!="],
+    &["<", "<=", ">", ">="],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = expr.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if c == '$' {
+            let start = i + 1;
+            i += 1;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Register(chars[start..i].iter().collect()));
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_alphanumeric() {
+                i += 1;
+            }
+            let literal: String = chars[start..i].iter().collect();
+            tokens.push(Token::Number(parse_number(&literal)?));
+        } else if c == '-' && chars.get(i + 1) == Some(&'>') {
+            tokens.push(Token::Arrow);
+            i += 2;
+        } else if let Some(op) = ["==", "!=", "<=", ">=", "&&", "||"]
+            .iter()
+            .find(|op| chars[i..].starts_with(&op.chars().collect::<Vec<char>>()))
+        {
+            tokens.push(Token::Op(op.to_string()));
+            i += 2;
+        } else if "+-/%<>!&".contains(c) {
+            tokens.push(Token::Op(c.to_string()));
+            i += 1;
+        } else {
+            tokens.push(match c {
+                '*' => Token::Star,
+                '.' => Token::Dot,
+                '[' => Token::LBracket,
+                ']' => Token::RBracket,
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                _ => return Err(format!("Invalid character '{}' in expression.", c)),
+            });
+            i += 1;
+        }
+    }
+    Ok(tokens)
+}
+
+/// Parses a decimal or 0x-prefixed hexadecimal integer literal.
+pub fn parse_number(literal: &str) -> Result<i64, String> {
+    let parsed = if literal.to_lowercase().starts_with("0x") {
+        i64::from_str_radix(&literal[2..], 16)
+    } else {
+        literal.parse::<i64>()
+    };
+    parsed.or(Err(format!("Invalid number \"{}\".", literal)))
+}
+
+fn parse(expr: &str) -> Result<Expr, String> {
+    let mut parser = Parser { tokens: tokenize(expr)?, pos: 0 };
+    let parsed = parser.parse_expr(0)?;
+    if parser.pos != parser.tokens.len() {
+        return Err(format!("Junk at end of expression \"{}\".", expr));
+    }
+    Ok(parsed)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next() {
+            Some(ref token) if *token == expected => Ok(()),
+            _ => Err(format!("Expected {:?} in expression.", expected)),
+        }
+    }
+
+    // expr := unary (binop unary)*, with the usual C precedence
+    fn parse_expr(&mut self, level: usize) -> Result<Expr, String> {
+        if level == PRECEDENCE.len() {
+            return self.parse_unary();
+        }
+        let mut expr = self.parse_expr(level + 1)?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Star) => "*".to_string(),
+                Some(Token::Op(op)) => op.clone(),
+                _ => return Ok(expr),
+            };
+            if !PRECEDENCE[level].contains(&op.as_str()) {
+                return Ok(expr);
+            }
+            self.pos += 1;
+            let rhs = self.parse_expr(level + 1)?;
+            expr = Expr::Binary(op, Box::new(expr), Box::new(rhs));
+        }
+    }
+
+    // unary := '*' unary | '&' unary | '-' unary | '!' unary | postfix
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            Some(Token::Star) => {
+                self.pos += 1;
+                Ok(Expr::Deref(Box::new(self.parse_unary()?)))
+            }
+            Some(Token::Op(op)) if op == "&" => {
+                self.pos += 1;
+                Ok(Expr::AddressOf(Box::new(self.parse_unary()?)))
+            }
+            Some(Token::Op(op)) if op == "-" || op == "!" => {
+                let op = op.clone();
+                self.pos += 1;
+                Ok(Expr::Unary(op, Box::new(self.parse_unary()?)))
+            }
+            _ => self.parse_postfix(),
+        }
+    }
+
+    // postfix := primary ('.' ident | '->' ident | '[' expr ']')*
+    fn parse_postfix(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_primary()?;
+        loop {
+            match self.peek() {
+                Some(Token::Dot) | Some(Token::Arrow) => {
+                    let arrow = self.next() == Some(Token::Arrow);
+                    let field = match self.next() {
+                        Some(Token::Ident(field)) => field,
+                        _ => return Err("Expected a field name in expression.".to_string()),
+                    };
+                    if arrow {
+                        expr = Expr::Deref(Box::new(expr));
+                    }
+                    expr = Expr::Member(Box::new(expr), field);
+                }
+                Some(Token::LBracket) => {
+                    self.pos += 1;
+                    let index = self.parse_expr(0)?;
+                    self.expect(Token::RBracket)?;
+                    expr = Expr::Index(Box::new(expr), Box::new(index));
+                }
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    // primary := ident | '$' ident | number | '(' expr ')'
+    fn parse_primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Ident(name)) => Ok(Expr::Var(name)),
+            Some(Token::Register(name)) => Ok(Expr::Register(name)),
+            Some(Token::Number(value)) => Ok(Expr::Number(value)),
+            Some(Token::LParen) => {
+                let expr = self.parse_expr(0)?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            _ => Err("Syntax error in expression.".to_string()),
+        }
+    }
+}
+
+/// Evaluates expressions in the context of one stack frame: `pc` picks the function whose
+/// locals are visible and `frame_base` is what their DW_OP_fbreg offsets are relative to.
+pub struct Evaluator<'a> {
+    debug_data: &'a DwarfData,
+    inferior: &'a Inferior,
+    pc: usize,
+    frame_base: usize,
+}
+
+impl<'a> Evaluator<'a> {
+    pub fn new(debug_data: &'a DwarfData, inferior: &'a Inferior, pc: usize, frame_base: usize)
+               -> Evaluator<'a> {
+        Evaluator { debug_data, inferior, pc, frame_base }
+    }
+
+    /// Evaluates `expr` and formats its value, e.g. `struct point {x = 1, y = 2}`.
+    pub fn print(&self, expr: &str) -> Result<String, String> {
+        let parsed = parse(expr)?;
+        match parsed {
+            Expr::Number(_) | Expr::Unary(_, _) | Expr::Binary(_, _, _) => {
+                Ok(self.value(&parsed)?.to_string())
+            }
+            Expr::AddressOf(_) => Ok(format!("{:#x}", self.value(&parsed)?)),
+            Expr::Register(ref name) => {
+                let value = self.value(&parsed)?;
+                match registers::canonical_name(name) {
+                    "rip" | "rsp" | "rbp" => Ok(format!("{:#x}", value)),
+                    _ => Ok(value.to_string()),
+                }
+            }
+            _ => self.print_lvalue(&self.eval(&parsed)?),
+        }
+    }
+
+    /// Evaluates `expr` as an integer, e.g. a breakpoint condition where non-zero means true.
+    pub fn integer_value(&self, expr: &str) -> Result<i64, String> {
+        self.value(&parse(expr)?)
+    }
+
+    pub fn print_variable(&self, var: &Variable) -> Result<String, String> {
+        self.print_lvalue(&self.variable(var))
+    }
+
+    fn print_lvalue(&self, lvalue: &LValue) -> Result<String, String> {
+        let bytes = self.read(lvalue)?;
+        let value = self.format(&lvalue.dtype, &bytes);
+        match self.debug_data.strip_typedefs(&lvalue.dtype).kind {
+            TypeKind::Struct(_) => Ok(format!("{} {}", lvalue.dtype.name, value)),
+            _ => Ok(value),
+        }
+    }
+
+    pub fn lvalue(&self, expr: &str) -> Result<LValue, String> {
+        self.eval(&parse(expr)?)
+    }
+
+    /// Where `var` lives in this frame.
+    pub fn variable(&self, var: &Variable) -> LValue {
+        let addr = match var.location {
+            Location::Address(addr) => addr,
+            Location::FramePointerOffset(offset) => (self.frame_base as isize + offset) as usize,
+        };
+        LValue { dtype: var.entity_type.clone(), addr }
+    }
+
+    /// Evaluates `expr` and encodes it as a value of type `dtype`, for assignment.
+    pub fn encode(&self, dtype: &Type, expr: &str) -> Result<Vec<u8>, String> {
+        let stripped = self.debug_data.strip_typedefs(dtype);
+        match &stripped.kind {
+            TypeKind::Base(Encoding::Float) => {
+                // our expressions have no floating point literals, so try a plain number first
+                let value = match expr.trim().parse::<f64>() {
+                    Ok(value) => value,
+                    Err(_) => self.integer_value(expr)? as f64,
+                };
+                match stripped.size {
+                    4 => Ok((value as f32).to_bits().to_le_bytes().to_vec()),
+                    8 => Ok(value.to_bits().to_le_bytes().to_vec()),
+                    size => Err(format!("Cannot assign to a {}-byte float.", size)),
+                }
+            }
+            TypeKind::Enum(enumerators) => {
+                let value = match enumerators.iter().find(|(name, _)| name == expr.trim()) {
+                    Some((_, value)) => *value,
+                    None => self.integer_value(expr)?,
+                };
+                Ok(value.to_le_bytes()[..stripped.size.min(8)].to_vec())
+            }
+            TypeKind::Base(_) | TypeKind::Pointer(_) => {
+                let value = self.integer_value(expr)?;
+                Ok(value.to_le_bytes()[..stripped.size.min(8)].to_vec())
+            }
+            _ => Err(format!("Cannot assign to a value of type {}.", dtype.name)),
+        }
+    }
+
+    pub fn read(&self, lvalue: &LValue) -> Result<Vec<u8>, String> {
+        self.inferior
+            .read_memory(lvalue.addr, lvalue.dtype.size)
+            .or(Err(format!("Cannot access memory at address {:#x}", lvalue.addr)))
+    }
+
+    fn eval(&self, expr: &Expr) -> Result<LValue, String> {
+        match expr {
+            Expr::Var(name) => match self.debug_data.get_variable(self.pc, name) {
+                Some(var) => Ok(self.variable(var)),
+                None => Err(format!("No symbol \"{}\" in current context.", name)),
+            },
+            Expr::Number(_) | Expr::Unary(_, _) | Expr::Binary(_, _, _) | Expr::AddressOf(_)
+            | Expr::Register(_) => {
+                Err("Attempt to take address of value not located in memory.".to_string())
+            }
+            Expr::Deref(inner) => self.element(self.eval(inner)?, 0),
+            Expr::Member(inner, field) => {
+                let lvalue = self.eval(inner)?;
+                let members = match &self.debug_data.strip_typedefs(&lvalue.dtype).kind {
+                    TypeKind::Struct(members) => members.clone(),
+                    _ => return Err(format!("Attempt to extract a component of a value that is \
+                                             not a structure.")),
+                };
+                let member = match members.iter().find(|member| &member.name == field) {
+                    Some(member) => member,
+                    None => return Err(format!("There is no member named {}.", field)),
+                };
+                Ok(LValue {
+                    dtype: self.get_type(member.type_offset)?,
+                    addr: lvalue.addr + member.offset,
+                })
+            }
+            Expr::Index(inner, index) => {
+                let index = self.value(index)?;
+                self.element(self.eval(inner)?, index)
+            }
+        }
+    }
+
+    /// Evaluates an expression whose value must be an integer.
+    fn value(&self, expr: &Expr) -> Result<i64, String> {
+        match expr {
+            Expr::Number(value) => Ok(*value),
+            Expr::AddressOf(inner) => Ok(self.eval(inner)?.addr as i64),
+            // $pc follows the selected frame; the other registers are the innermost frame's
+            Expr::Register(name) if registers::canonical_name(name) == "rip" => Ok(self.pc as i64),
+            Expr::Register(name) => {
+                let regs = self.inferior
+                    .get_regs()
+                    .or(Err("Couldn't read the registers.".to_string()))?;
+                match registers::get_register(&regs, name) {
+                    Some(value) => Ok(value as i64),
+                    None => Err(format!("Invalid register \"${}\".", name)),
+                }
+            }
+            Expr::Unary(op, inner) => {
+                let value = self.value(inner)?;
+                Ok(if op == "-" { value.wrapping_neg() } else { (value == 0) as i64 })
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = self.value(lhs)?;
+                // && and || don't evaluate their right side unless they need it, like C
+                match op.as_str() {
+                    "&&" if lhs == 0 => return Ok(0),
+                    "||" if lhs != 0 => return Ok(1),
+                    _ => {}
+                }
+                let rhs = self.value(rhs)?;
+                Ok(match op.as_str() {
+                    "+" => lhs.wrapping_add(rhs),
+                    "-" => lhs.wrapping_sub(rhs),
+                    "*" => lhs.wrapping_mul(rhs),
+                    "/" | "%" if rhs == 0 => return Err("Division by zero".to_string()),
+                    "/" => lhs.wrapping_div(rhs),
+                    "%" => lhs.wrapping_rem(rhs),
+                    "==" => (lhs == rhs) as i64,
+                    "!=" => (lhs != rhs) as i64,
+                    "<" => (lhs < rhs) as i64,
+                    "<=" => (lhs <= rhs) as i64,
+                    ">" => (lhs > rhs) as i64,
+                    ">=" => (lhs >= rhs) as i64,
+                    _ => (rhs != 0) as i64, // && and || with the left side undecided
+                })
+            }
+            // a function name stands for its address
+            Expr::Var(name) if self.debug_data.get_variable(self.pc, name).is_none() => {
+                match self.debug_data.get_addr_for_function(None, name) {
+                    Some(addr) => Ok(addr as i64),
+                    None => Err(format!("No symbol \"{}\" in current context.", name)),
+                }
+            }
+            _ => {
+                let lvalue = self.eval(expr)?;
+                self.integer(&lvalue)
+            }
+        }
+    }
+
+    /// Element `index` of an array or of the memory a pointer points to.
+    fn element(&self, lvalue: LValue, index: i64) -> Result<LValue, String> {
+        let (elem_type, base) = match &self.debug_data.strip_typedefs(&lvalue.dtype).kind {
+            TypeKind::Array(elem, dims) => (self.array_element_type(*elem, dims)?, lvalue.addr),
+            TypeKind::Pointer(Some(pointee)) => {
+                let bytes = self.read(&lvalue)?;
+                (self.get_type(*pointee)?, to_u64(&bytes) as usize)
+            }
+            TypeKind::Pointer(None) => {
+                return Err("Attempt to take contents of a non-pointer value.".to_string())
+            }
+            _ => return Err(format!("Cannot subscript or dereference a value of type {}.",
+                                    lvalue.dtype.name)),
+        };
+        let addr = (base as i64).wrapping_add(index.wrapping_mul(elem_type.size as i64));
+        Ok(LValue { dtype: elem_type, addr: addr as usize })
+    }
+
+    /// Reads an integer-like value (integers, chars, bools, enums and pointers).
+    pub fn integer(&self, lvalue: &LValue) -> Result<i64, String> {
+        let bytes = self.read(lvalue)?;
+        match &self.debug_data.strip_typedefs(&lvalue.dtype).kind {
+            TypeKind::Base(Encoding::Float) => Err("Expected an integer.".to_string()),
+            TypeKind::Base(Encoding::Signed) | TypeKind::Base(Encoding::SignedChar)
+            | TypeKind::Enum(_) => Ok(to_i64(&bytes)),
+            TypeKind::Base(_) | TypeKind::Pointer(_) => Ok(to_u64(&bytes) as i64),
+            _ => Err(format!("Expected an integer, not {}.", lvalue.dtype.name)),
+        }
+    }
+
+    fn get_type(&self, offset: usize) -> Result<Type, String> {
+        self.debug_data
+            .get_type(offset)
+            .cloned()
+            .ok_or(format!("Unknown type at offset {:#x}", offset))
+    }
+
+    /// The type of one element of an array: another array for all but the last dimension.
+    fn array_element_type(&self, elem: usize, dims: &[usize]) -> Result<Type, String> {
+        let elem_type = self.get_type(elem)?;
+        if dims.len() <= 1 {
+            return Ok(elem_type);
+        }
+        let inner_dims = dims[1..].to_vec();
+        let dims_str: Vec<String> = inner_dims.iter().map(|dim| format!("[{}]", dim)).collect();
+        let size = elem_type.size * inner_dims.iter().product::<usize>();
+        Ok(Type::new(
+            format!("{} {}", elem_type.name, dims_str.join("")),
+            size,
+            TypeKind::Array(elem, inner_dims),
+        ))
+    }
+
+    /// Formats the raw little-endian bytes of a value of type `dtype`.
+    pub fn format(&self, dtype: &Type, bytes: &[u8]) -> String {
+        match &self.debug_data.strip_typedefs(dtype).kind {
+            TypeKind::Base(encoding) => format_base(*encoding, bytes),
+            TypeKind::Pointer(pointee) => {
+                let addr = to_u64(bytes) as usize;
+                let points_to_char = pointee
+                    .and_then(|pointee| self.debug_data.get_type(pointee))
+                    .map_or(false, |pointee| self.is_char(pointee));
+                // print has no use for breakpoint bytes; it reads memory as it is
+                let string = if addr != 0 && points_to_char {
+                    self.inferior.read_c_string(addr, MAX_ELEMENTS, &HashMap::new()).ok()
+                } else {
+                    None
+                };
+                match string {
+                    Some((string, true)) => format!("{:#x} {:?}", addr, string),
+                    Some((string, false)) => format!("{:#x} {:?}...", addr, string),
+                    None => format!("{:#x}", addr),
+                }
+            }
+            TypeKind::Struct(members) => {
+                let fields: Vec<String> = members
+                    .iter()
+                    .map(|member| {
+                        let value = match self.debug_data.get_type(member.type_offset) {
+                            Some(member_type) => {
+                                match bytes.get(member.offset..member.offset + member_type.size) {
+                                    Some(member_bytes) => self.format(member_type, member_bytes),
+                                    None => "<unavailable>".to_string(),
+                                }
+                            }
+                            None => "<unknown type>".to_string(),
+                        };
+                        format!("{} = {}", member.name, value)
+                    })
+                    .collect();
+                format!("{{{}}}", fields.join(", "))
+            }
+            TypeKind::Array(elem, dims) => {
+                let elem_type = match self.array_element_type(*elem, dims) {
+                    Ok(elem_type) => elem_type,
+                    Err(err) => return format!("<{}>", err),
+                };
+                if elem_type.size == 0 {
+                    return "{}".to_string();
+                }
+                if self.is_char(&elem_type) {
+                    let string: String = bytes
+                        .iter()
+                        .take_while(|byte| **byte != 0)
+                        .map(|byte| *byte as char)
+                        .collect();
+                    return format!("{:?}", string);
+                }
+                let mut elements: Vec<String> = bytes
+                    .chunks(elem_type.size)
+                    .take(MAX_ELEMENTS)
+                    .map(|chunk| self.format(&elem_type, chunk))
+                    .collect();
+                if bytes.len() / elem_type.size > MAX_ELEMENTS {
+                    elements.push("...".to_string());
+                }
+                format!("{{{}}}", elements.join(", "))
+            }
+            TypeKind::Enum(enumerators) => {
+                let value = to_i64(bytes);
+                match enumerators.iter().find(|(_, enum_value)| *enum_value == value) {
+                    Some((name, _)) => name.clone(),
+                    None => format!("{}", value),
+                }
+            }
+            TypeKind::Typedef(None) | TypeKind::Qualified(None) => "<incomplete type>".to_string(),
+            TypeKind::Typedef(Some(_)) | TypeKind::Qualified(Some(_)) => {
+                "<unknown type>".to_string()
+            }
+        }
+    }
+
+    fn is_char(&self, dtype: &Type) -> bool {
+        match self.debug_data.strip_typedefs(dtype).kind {
+            TypeKind::Base(Encoding::SignedChar) | TypeKind::Base(Encoding::UnsignedChar) => true,
+            _ => false,
+        }
+    }
+}
+
+pub fn to_u64(bytes: &[u8]) -> u64 {
+    let mut buf = [0u8; 8];
+    let len = bytes.len().min(8);
+    buf[..len].copy_from_slice(&bytes[..len]);
+    u64::from_le_bytes(buf)
+}
+
+/// Sign-extends a little-endian integer of up to 8 bytes.
+pub fn to_i64(bytes: &[u8]) -> i64 {
+    let len = bytes.len().min(8);
+    if len == 0 {
+        return 0;
+    }
+    let shift = 64 - 8 * len as u32;
+    ((to_u64(bytes) << shift) as i64) >> shift
+}
+
+fn format_base(encoding: Encoding, bytes: &[u8]) -> String {
+    if bytes.len() > 8 || bytes.is_empty() {
+        let hex: Vec<String> = bytes.iter().rev().map(|byte| format!("{:02x}", byte)).collect();
+        return format!("0x{}", hex.join(""));
+    }
+    let raw = to_u64(bytes);
+    match encoding {
+        Encoding::Float => match bytes.len() {
+            4 => format!("{}", f32::from_bits(raw as u32)),
+            _ => format!("{}", f64::from_bits(raw)),
+        },
+        Encoding::Boolean => format!("{}", raw != 0),
+        Encoding::SignedChar => format!("{} {:?}", to_i64(bytes), raw as u8 as char),
+        Encoding::UnsignedChar => format!("{} {:?}", raw, raw as u8 as char),
+        Encoding::Signed => format!("{}", to_i64(bytes)),
+        Encoding::Unsigned => format!("{}", raw),
+    }
+}
@@ -0,0 +1,162 @@
+//! The `x/NFU addr` command: dumps the inferior's memory as numbers, characters, strings or
+//! disassembled instructions, the way gdb does.
+
+use crate::dwarf_data::DwarfData;
+use crate::eval::{to_i64, to_u64, MAX_ELEMENTS};
+use crate::inferior::Inferior;
+use iced_x86::{Code, Decoder, DecoderOptions, Formatter, GasFormatter};
+use std::collections::HashMap;
+
+/// Longest x86-64 instruction
+pub const MAX_INSN_LEN: usize = 15;
+
+/// The N, F and U of `x/NFU`: how many units to show, in which format and how big each one is.
+#[derive(Clone, Copy)]
+pub struct ExamineFormat {
+    pub count: usize,
+    pub format: char,
+    pub size: usize,
+}
+
+impl Default for ExamineFormat {
+    fn default() -> Self {
+        ExamineFormat { count: 1, format: 'x', size: 4 }
+    }
+}
+
+impl ExamineFormat {
+    /// Parses what follows the slash of `x/NFU`. Like gdb, the format and unit size default to
+    /// those of the previous x command, and the count to 1.
+    pub fn parse(spec: &str, last: ExamineFormat) -> Result<ExamineFormat, String> {
+        let digits: String = spec.chars().take_while(|c| c.is_ascii_digit()).collect();
+        let count = match digits.as_str() {
+            "" => 1,
+            _ => digits.parse::<usize>().or(Err(format!("Invalid number \"{}\".", digits)))?,
+        };
+        let mut fmt = ExamineFormat { count, ..last };
+        let mut size_given = false;
+        for c in spec[digits.len()..].chars() {
+            match c {
+                'x' | 'd' | 'u' | 'o' | 'c' | 's' | 'i' => fmt.format = c,
+                'b' | 'h' | 'w' | 'g' => {
+                    fmt.size = match c {
+                        'b' => 1,
+                        'h' => 2,
+                        'w' => 4,
+                        _ => 8,
+                    };
+                    size_given = true;
+                }
+                _ => return Err(format!("Undefined output format \"{}\".", c)),
+            }
+        }
+        if fmt.format == 'c' && !size_given {
+            fmt.size = 1;
+        }
+        Ok(fmt)
+    }
+}
+
+pub struct Examiner<'a> {
+    debug_data: &'a DwarfData,
+    inferior: &'a Inferior,
+    brk_point_map: &'a HashMap<usize, u8>,
+    /// Marked with "=>" when disassembling
+    pc: Option<usize>,
+}
+
+impl<'a> Examiner<'a> {
+    pub fn new(debug_data: &'a DwarfData, inferior: &'a Inferior,
+               brk_point_map: &'a HashMap<usize, u8>, pc: Option<usize>) -> Examiner<'a> {
+        Examiner { debug_data, inferior, brk_point_map, pc }
+    }
+
+    /// Prints memory starting at `addr` in format `fmt` and returns the address right after
+    /// what was shown, where a following bare `x` continues.
+    pub fn examine(&self, addr: usize, fmt: &ExamineFormat) -> Result<usize, String> {
+        match fmt.format {
+            's' => self.examine_strings(addr, fmt.count),
+            'i' => self.examine_instructions(addr, fmt.count),
+            _ => self.examine_units(addr, fmt),
+        }
+    }
+
+    fn examine_units(&self, addr: usize, fmt: &ExamineFormat) -> Result<usize, String> {
+        let per_line = match fmt.size {
+            8 => 2,
+            4 => 4,
+            _ => 8,
+        };
+        let bytes = self.read(addr, fmt.count * fmt.size)?;
+        for (line_num, line) in bytes.chunks(per_line * fmt.size).enumerate() {
+            let line_addr = addr + line_num * per_line * fmt.size;
+            let mut output = format!("{}:", self.debug_data.get_label_for_addr(line_addr));
+            for unit in line.chunks(fmt.size) {
+                let value = match fmt.format {
+                    'x' => format!("{:#0width$x}", to_u64(unit), width = 2 + 2 * fmt.size),
+                    'd' => format!("{}", to_i64(unit)),
+                    'u' => format!("{}", to_u64(unit)),
+                    'o' if to_u64(unit) == 0 => "0".to_string(),
+                    'o' => format!("0{:o}", to_u64(unit)),
+                    _ => format!("{} {:?}", to_i64(&unit[..1]), unit[0] as char),
+                };
+                output.push('\t');
+                output.push_str(&value);
+            }
+            println!("{}", output);
+        }
+        Ok(addr + bytes.len())
+    }
+
+    fn examine_strings(&self, mut addr: usize, count: usize) -> Result<usize, String> {
+        for _ in 0..count {
+            let (string, terminated) = self.inferior
+                .read_c_string(addr, MAX_ELEMENTS, self.brk_point_map)
+                .or(Err(format!("Cannot access memory at address {:#x}", addr)))?;
+            let ellipsis = if terminated { "" } else { "..." };
+            let label = self.debug_data.get_label_for_addr(addr);
+            println!("{}:\t{:?}{}", label, string, ellipsis);
+            // past the NUL too
+            addr += string.chars().count() + terminated as usize;
+        }
+        Ok(addr)
+    }
+
+    fn examine_instructions(&self, mut addr: usize, count: usize) -> Result<usize, String> {
+        let mut formatter = GasFormatter::new();
+        formatter.options_mut().set_first_operand_char_index(7);
+        for _ in 0..count {
+            // an instruction may end right before unmapped memory, so read no further than the
+            // end of the page if we have to
+            let bytes = match self.read(addr, MAX_INSN_LEN) {
+                Ok(bytes) => bytes,
+                Err(err) => {
+                    let page_end = (addr | 0xfff) + 1;
+                    if page_end - addr >= MAX_INSN_LEN {
+                        return Err(err);
+                    }
+                    self.read(addr, page_end - addr)?
+                }
+            };
+            let mut decoder = Decoder::new(64, &bytes, DecoderOptions::NONE);
+            decoder.set_ip(addr as u64);
+            let instruction = decoder.decode();
+            let mut text = String::new();
+            if instruction.code() == Code::INVALID {
+                text.push_str("(bad)");
+            } else {
+                formatter.format(&instruction, &mut text);
+            }
+            let marker = if self.pc == Some(addr) { "=> " } else { "   " };
+            println!("{}{}:\t{}", marker, self.debug_data.get_label_for_addr(addr), text);
+            addr += instruction.len().max(1);
+        }
+        Ok(addr)
+    }
+
+    fn read(&self, addr: usize, len: usize) -> Result<Vec<u8>, String> {
+        self.inferior
+            .read_original_memory(addr, len, self.brk_point_map)
+            .or(Err(format!("Cannot access memory at address {:#x}", addr)))
+    }
+}
@@ -10,13 +10,18 @@ use gimli::{UnitOffset, UnitSectionOffset};
 use object::Object;
 use std::borrow;
 //use std::io::{BufWriter, Write};
-use crate::dwarf_data::{File, Function, Line, Location, Type, Variable};
+use crate::dwarf_data::{
+    Encoding, File, Function, Line, Location, Member, Type, TypeKind, Variable, MAX_TYPE_DEPTH,
+};
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::fmt::Write;
 use std::{io, path};
 
-pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<Vec<File>, Error> {
+pub fn load_file(
+    object: &object::File,
+    endian: gimli::RunTimeEndian,
+) -> Result<(Vec<File>, HashMap<usize, Type>), Error> {
     // Load a section and return as `Cow<[u8]>`.
     let load_section = |id: gimli::SectionId| -> Result<borrow::Cow<[u8]>, gimli::Error> {
         Ok(object
@@ -49,11 +54,120 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
     while let Some(header) = iter.next()? {
         let unit = dwarf.unit(header)?;
 
+        // Collect the types first, since variables may refer to types declared after them
+        let mut raw_types: HashMap<usize, RawType> = HashMap::new();
+        // Enclosing struct/array/enum DIEs (depth, offset), to attach members, subranges and
+        // enumerators to
+        let mut parents: Vec<(isize, usize)> = Vec::new();
+        let mut depth = 0;
+        let mut entries = unit.entries();
+        while let Some((delta_depth, entry)) = entries.next_dfs()? {
+            depth += delta_depth;
+            while parents.last().map_or(false, |&(parent_depth, _)| parent_depth >= depth) {
+                parents.pop();
+            }
+            let parent = match parents.last() {
+                Some(&(parent_depth, offset)) if parent_depth == depth - 1 => Some(offset),
+                _ => None,
+            };
+            let type_offset = section_offset(entry.offset(), &unit);
+            let name = get_name(entry, &unit, &dwarf);
+            let byte_size = get_udata(entry, gimli::DW_AT_byte_size);
+            let inner = get_type_offset(entry, &unit, &dwarf);
+            let (name, kind) = match entry.tag() {
+                gimli::DW_TAG_base_type => {
+                    let encoding = match entry.attr_value(gimli::DW_AT_encoding)? {
+                        Some(gimli::AttributeValue::Encoding(gimli::DW_ATE_float)) => Encoding::Float,
+                        Some(gimli::AttributeValue::Encoding(gimli::DW_ATE_boolean)) => Encoding::Boolean,
+                        Some(gimli::AttributeValue::Encoding(gimli::DW_ATE_signed_char)) => Encoding::SignedChar,
+                        Some(gimli::AttributeValue::Encoding(gimli::DW_ATE_unsigned_char)) => Encoding::UnsignedChar,
+                        Some(gimli::AttributeValue::Encoding(gimli::DW_ATE_signed)) => Encoding::Signed,
+                        _ => Encoding::Unsigned,
+                    };
+                    (name.unwrap_or("<unknown>".to_string()), TypeKind::Base(encoding))
+                }
+                gimli::DW_TAG_pointer_type => (String::new(), TypeKind::Pointer(inner)),
+                gimli::DW_TAG_typedef => {
+                    (name.unwrap_or("<unknown>".to_string()), TypeKind::Typedef(inner))
+                }
+                gimli::DW_TAG_const_type => ("const".to_string(), TypeKind::Qualified(inner)),
+                gimli::DW_TAG_volatile_type => ("volatile".to_string(), TypeKind::Qualified(inner)),
+                gimli::DW_TAG_structure_type | gimli::DW_TAG_union_type => {
+                    parents.push((depth, type_offset));
+                    let keyword = if entry.tag() == gimli::DW_TAG_union_type { "union" } else { "struct" };
+                    let name = name.unwrap_or("<anonymous>".to_string());
+                    (format!("{} {}", keyword, name), TypeKind::Struct(Vec::new()))
+                }
+                gimli::DW_TAG_enumeration_type => {
+                    parents.push((depth, type_offset));
+                    let name = name.unwrap_or("<anonymous>".to_string());
+                    (format!("enum {}", name), TypeKind::Enum(Vec::new()))
+                }
+                gimli::DW_TAG_array_type => {
+                    parents.push((depth, type_offset));
+                    (String::new(), TypeKind::Array(inner.unwrap_or(0), Vec::new()))
+                }
+                gimli::DW_TAG_member => {
+                    if let (Some(parent), Some(member_type)) = (parent, inner) {
+                        if let Some(RawType { kind: TypeKind::Struct(members), .. }) =
+                            raw_types.get_mut(&parent)
+                        {
+                            members.push(Member {
+                                name: name.unwrap_or("<anonymous>".to_string()),
+                                type_offset: member_type,
+                                offset: get_udata(entry, gimli::DW_AT_data_member_location)
+                                    .unwrap_or(0),
+                            });
+                        }
+                    }
+                    continue;
+                }
+                gimli::DW_TAG_subrange_type => {
+                    if let Some(parent) = parent {
+                        if let Some(RawType { kind: TypeKind::Array(_, dims), .. }) =
+                            raw_types.get_mut(&parent)
+                        {
+                            // flexible array members have neither a count nor an upper bound
+                            let len = get_udata(entry, gimli::DW_AT_count).unwrap_or(
+                                get_udata(entry, gimli::DW_AT_upper_bound).map_or(0, |upper| upper + 1),
+                            );
+                            dims.push(len);
+                        }
+                    }
+                    continue;
+                }
+                gimli::DW_TAG_enumerator => {
+                    if let Some(parent) = parent {
+                        if let Some(RawType { kind: TypeKind::Enum(enumerators), .. }) =
+                            raw_types.get_mut(&parent)
+                        {
+                            let value = match entry.attr(gimli::DW_AT_const_value)? {
+                                Some(attr) => attr.sdata_value().unwrap_or(0),
+                                None => 0,
+                            };
+                            enumerators.push((name.unwrap_or("<unknown>".to_string()), value));
+                        }
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            raw_types.insert(type_offset, RawType { name, byte_size, kind });
+        }
+        for type_offset in raw_types.keys() {
+            resolve_type(*type_offset, &raw_types, &mut offset_to_type, 0);
+        }
+
         // Iterate over the Debugging Information Entries (DIEs) in the unit.
         let mut depth = 0;
+        // Depth of the outermost function DIE we are inside of, if any
+        let mut function_depth: Option<isize> = None;
         let mut entries = unit.entries();
         while let Some((delta_depth, entry)) = entries.next_dfs()? {
             depth += delta_depth;
+            if function_depth.map_or(false, |function_depth| function_depth >= depth) {
+                function_depth = None;
+            }
             // Update the offset_to_type mapping for types
             // Update the variable list for formal params/variables
             match entry.tag() {
@@ -67,41 +181,23 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                     } else {
                         "<unknown>".to_string()
                     };
+                    let comp_dir = match entry.attr(gimli::DW_AT_comp_dir) {
+                        Ok(Some(attr)) => match get_attr_value(&attr, &unit, &dwarf) {
+                            Ok(DebugValue::Str(dir)) => dir,
+                            _ => String::new(),
+                        },
+                        _ => String::new(),
+                    };
                     compilation_units.push(File {
                         name,
+                        comp_dir,
                         global_variables: Vec::new(),
                         functions: Vec::new(),
                         lines: Vec::new(),
                     });
                 }
-                gimli::DW_TAG_base_type => {
-                    let name = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_name) {
-                        if let Ok(DebugValue::Str(name)) = get_attr_value(&attr, &unit, &dwarf) {
-                            name
-                        } else {
-                            "<unknown>".to_string()
-                        }
-                    } else {
-                        "<unknown>".to_string()
-                    };
-                    let byte_size = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_byte_size) {
-                        if let Ok(DebugValue::Uint(byte_size)) =
-                            get_attr_value(&attr, &unit, &dwarf)
-                        {
-                            byte_size
-                        } else {
-                            // TODO: report error?
-                            0
-                        }
-                    } else {
-                        // TODO: report error?
-                        0
-                    };
-                    let type_offset = entry.offset().0;
-                    offset_to_type
-                        .insert(type_offset, Type::new(name, byte_size.try_into().unwrap()));
-                }
                 gimli::DW_TAG_subprogram => {
+                    function_depth = function_depth.or(Some(depth));
                     let mut func: Function = Default::default();
                     let mut attrs = entry.attrs();
                     while let Some(attr) = attrs.next()? {
@@ -176,21 +272,25 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                             location: location.unwrap(),
                             line_number: line_number.try_into().unwrap(),
                         };
-                        if depth == 1 {
+                        // statics in a namespace are nested too, but outside any function
+                        if function_depth.is_none() {
                             compilation_units
                                 .last_mut()
                                 .unwrap()
                                 .global_variables
                                 .push(var);
-                        } else if depth > 1 {
-                            compilation_units
+                        } else {
+                            let func = compilation_units
                                 .last_mut()
                                 .unwrap()
                                 .functions
                                 .last_mut()
-                                .unwrap()
-                                .variables
-                                .push(var);
+                                .unwrap();
+                            if entry.tag() == gimli::DW_TAG_formal_parameter {
+                                func.args.push(var);
+                            } else {
+                                func.variables.push(var);
+                            }
                         }
                     }
                 }
@@ -220,27 +320,40 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                         );
                     }
 
-                    // Get the File
-                    let file = compilation_units
-                        .iter_mut()
-                        .find(|f| f.name == path.as_os_str().to_str().unwrap());
+                    // Get the File. Its name may be relative to the compilation directory.
+                    let file = compilation_units.iter_mut().find(|f| {
+                        path == path::Path::new(&f.name)
+                            || path == path::Path::new(&f.comp_dir).join(&f.name)
+                    });
 
                     // Determine line/column. DWARF line/column is never 0, so we use that
                     // but other applications may want to display this differently.
                     let line = row.line().unwrap_or(0);
 
-                    if let Some(file) = file {
-                        file.lines.push(Line {
-                            file: file.name.clone(),
-                            number: line.try_into().unwrap(),
-                            address: row.address().try_into().unwrap(),
-                        });
-                    }
+                    // Rows from other files, like headers, or every row of a Rust unit, whose
+                    // name isn't a source file, go with the unit under their own path.
+                    let (file, name) = match file {
+                        Some(file) => {
+                            let name = file.name.clone();
+                            (file, name)
+                        }
+                        None => {
+                            let file = compilation_units.last_mut().unwrap();
+                            let name = path::Path::new(&file.comp_dir).join(&path);
+                            (file, name.to_string_lossy().into_owned())
+                        }
+                    };
+                    file.lines.push(Line {
+                        file: name,
+                        number: line.try_into().unwrap(),
+                        address: row.address().try_into().unwrap(),
+                        prologue_end: row.prologue_end(),
+                    });
                 }
             }
         }
     }
-    Ok(compilation_units)
+    Ok((compilation_units, offset_to_type))
 }
 
 #[derive(Debug, Clone)]
@@ -291,6 +404,98 @@ impl<'input, Endian> Reader for gimli::EndianSlice<'input, Endian> where
 
 trait Reader: gimli::Reader<Offset = usize> + Send + Sync {}
 
+fn section_offset<R: Reader>(offset: UnitOffset, unit: &gimli::Unit<R>) -> usize {
+    match offset.to_unit_section_offset(unit) {
+        UnitSectionOffset::DebugInfoOffset(goff) => goff.0,
+        UnitSectionOffset::DebugTypesOffset(goff) => goff.0,
+    }
+}
+
+/// A type as it appears in the DIE, before the types it refers to have been resolved.
+struct RawType {
+    name: String, // the qualifier for const/volatile, unused for pointers and arrays
+    byte_size: Option<usize>,
+    kind: TypeKind,
+}
+
+/// Computes the name and size of the type at `offset`, resolving the types it is built from
+/// first.
+fn resolve_type(
+    offset: usize,
+    raw_types: &HashMap<usize, RawType>,
+    types: &mut HashMap<usize, Type>,
+    depth: usize,
+) -> Option<Type> {
+    if let Some(dtype) = types.get(&offset) {
+        return Some(dtype.clone());
+    }
+    if depth > MAX_TYPE_DEPTH {
+        return None;
+    }
+    let raw = raw_types.get(&offset)?;
+    let mut resolve_inner = |inner: Option<usize>| {
+        inner.and_then(|inner| resolve_type(inner, raw_types, types, depth + 1))
+    };
+    let (name, size) = match &raw.kind {
+        TypeKind::Pointer(pointee) => {
+            let pointee_name = match pointee {
+                Some(_) => resolve_inner(*pointee).map_or("<unknown>".to_string(), |t| t.name),
+                None => "void".to_string(),
+            };
+            if pointee_name.ends_with('*') {
+                (format!("{}*", pointee_name), 8)
+            } else {
+                (format!("{} *", pointee_name), 8)
+            }
+        }
+        TypeKind::Typedef(inner) => (raw.name.clone(), resolve_inner(*inner).map_or(0, |t| t.size)),
+        TypeKind::Qualified(inner) => match resolve_inner(*inner) {
+            Some(inner) => (format!("{} {}", raw.name, inner.name), inner.size),
+            None => (format!("{} void", raw.name), 0),
+        },
+        TypeKind::Array(elem, dims) => {
+            let elem = resolve_inner(Some(*elem))?;
+            let dims_str: Vec<String> = dims.iter().map(|dim| format!("[{}]", dim)).collect();
+            let len: usize = dims.iter().product();
+            (format!("{} {}", elem.name, dims_str.join("")), elem.size * len)
+        }
+        _ => (raw.name.clone(), raw.byte_size.unwrap_or(0)),
+    };
+    let dtype = Type::new(name, size, raw.kind.clone());
+    types.insert(offset, dtype.clone());
+    Some(dtype)
+}
+
+fn get_name<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<String> {
+    match get_attr_value(&entry.attr(gimli::DW_AT_name).ok()??, unit, dwarf) {
+        Ok(DebugValue::Str(name)) => Some(name),
+        _ => None,
+    }
+}
+
+/// Returns the .debug_info offset of the type referred to by DW_AT_type.
+fn get_type_offset<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<usize> {
+    match get_attr_value(&entry.attr(gimli::DW_AT_type).ok()??, unit, dwarf) {
+        Ok(DebugValue::Size(offset)) => Some(offset),
+        _ => None,
+    }
+}
+
+fn get_udata<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    name: gimli::DwAt,
+) -> Option<usize> {
+    entry.attr(name).ok()??.udata_value()?.try_into().ok()
+}
+
 fn get_location<R: Reader>(attr: &gimli::Attribute<R>, unit: &gimli::Unit<R>) -> Option<Location> {
     if let gimli::AttributeValue::Exprloc(ref data) = attr.value() {
         let encoding = unit.encoding();
@@ -1,15 +1,18 @@
-use crate::dwarf_data::{DwarfData, Error as DwarfError};
+use crate::dwarf_data::{DwarfData, Line};
+use crate::signals::{self, SignalTable};
+use crate::unwind;
 use std::mem::size_of;
 use nix::sys::ptrace;
 use nix::sys::signal;
 use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
 use nix::unistd::Pid;
-use std::process::{Child, Command};
+use std::process::Command;
 use std::os::unix::process::CommandExt;
 use nix::sys::stat::stat;
 use core::num::FpCategory::Infinite;
 use std::collections::HashMap;
 use std::borrow::Borrow;
+use std::fmt;
 
 pub enum Status {
     /// Indicates inferior stopped. Contains the signal that stopped the process, as well as the
@@ -24,9 +27,76 @@ pub enum Status {
     Signaled(signal::Signal),
 }
 
+/// Offset of `u_debugreg` in `struct user` on x86-64, for PTRACE_PEEKUSER and PTRACE_POKEUSER.
+const DEBUG_REG_OFFSET: usize = 848;
+/// DR6 bits 0-3 say which of DR0-DR3 triggered a debug exception.
+const DR6_HIT_MASK: usize = 0xf;
+
+/// How many watchpoints the hardware can hold at once (DR0-DR3).
+pub const NUM_HW_WATCHPOINTS: usize = 4;
+
+/// What accesses a hardware watchpoint traps on. x86 can't trap on reads alone, so read
+/// watchpoints use `ReadWrite` as well.
+#[derive(Clone, Copy, PartialEq)]
+pub enum WatchAccess {
+    Write,
+    ReadWrite,
+}
+
+/// What happens to the two processes when the inferior forks, as set with `set follow-fork-mode`
+/// and `set detach-on-fork`. The process not followed is either detached or kept stopped.
+#[derive(Clone, Copy)]
+pub struct ForkSettings {
+    pub follow_child: bool,
+    pub detach_on_fork: bool,
+}
+
+impl Default for ForkSettings {
+    fn default() -> Self {
+        ForkSettings { follow_child: false, detach_on_fork: true }
+    }
+}
+
+/// Deepest stack we are willing to unwind, in case the frame pointer chain is corrupt.
+const MAX_FRAMES: usize = 256;
+
+/// One unwound stack frame.
+#[derive(Clone)]
+pub struct Frame {
+    /// Where execution is (innermost frame) or will resume (callers) in this frame
+    pub pc: usize,
+    /// Canonical frame address: %rsp before the call into this frame. gcc uses it as the frame
+    /// base that variable locations are relative to.
+    pub cfa: usize,
+    pub function: Option<String>,
+    pub line: Option<Line>,
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let function = self.function.as_ref().map_or("??", |name| name.as_str());
+        match &self.line {
+            Some(line) => write!(f, "{} ({})", function, line),
+            None => write!(f, "{:#x} in {}", self.pc, function),
+        }
+    }
+}
+
+/// Returns the path of the executable `pid` is running, or its /proc/<pid>/exe link if that
+/// can't be resolved.
+pub fn executable_path(pid: Pid) -> String {
+    let link = format!("/proc/{}/exe", pid);
+    std::fs::read_link(&link).map_or(link, |path| path.to_string_lossy().into_owned())
+}
+
 /// This function calls ptrace with PTRACE_TRACEME to enable debugging on a process. You should use
 /// pre_exec with Command to call this in the child process.
 fn child_traceme() -> Result<(), std::io::Error> {
+    // like gdb, keep addresses the same from one run to the next
+    unsafe {
+        let persona = libc::personality(0xffffffff) as libc::c_ulong;
+        libc::personality(persona | libc::ADDR_NO_RANDOMIZE as libc::c_ulong);
+    }
     ptrace::traceme().or(Err(std::io::Error::new(
         std::io::ErrorKind::Other,
         "ptrace TRACEME failed",
@@ -34,17 +104,47 @@ fn child_traceme() -> Result<(), std::io::Error> {
 }
 
 pub struct Inferior {
-    child: Child,
+    pid: Pid,
+    /// The thread we report on and step: the last one to stop, or the one picked with `thread`
+    tid: Pid,
+    /// Every thread of the inferior with the number it goes by, in the order they appeared
+    threads: Vec<(usize, Pid)>,
+    next_thread_num: usize,
+    /// Whether we attached to a process that was already running rather than starting it
+    attached: bool,
     is_exited: bool,
     is_brk_point: bool,
-    brk_point_rip: Option<usize>
+    brk_point_rip: Option<usize>,
+    /// The thread that hit `brk_point_rip`
+    brk_point_tid: Pid,
+    /// Signals to deliver to threads when they resume, as the signal table says to pass them
+    pending_signals: Vec<(Pid, signal::Signal)>,
+    signal_table: SignalTable,
+    /// Processes whose first stop came before the fork event that created them
+    early_stops: Vec<Pid>,
+    watch_hit: Option<usize>,
+    /// What DR0-DR3 were last programmed with, for processes we follow across a fork
+    hw_watches: Vec<(usize, usize, WatchAccess)>,
+    fork_settings: ForkSettings,
+    /// The PTRACE_EVENT_* behind the last stop, if it was one
+    ptrace_event: Option<i32>,
+    /// Set when the inferior has just exec'd a new program
+    exec_pending: bool,
+    /// Threads of the processes left stopped after a fork because detach-on-fork is off, leader
+    /// first
+    held: Vec<Vec<Pid>>,
+    /// Threads of a vfork parent we moved on from. It shares our memory until we exec or exit,
+    /// so it keeps our breakpoints until then.
+    vfork_parent: Option<Vec<Pid>>,
+    /// Whether our breakpoints are out of memory shared with a detached vfork child
+    vfork_brk_points_removed: bool,
 }
 
 impl Inferior {
-    /// Attempts to start a new inferior process. Returns Some(Inferior) if successful, or None if
-    /// an error is encountered.
-    pub fn new(target: &str, args: &Vec<String>, break_points: &Vec<usize>,
-               brk_point_map: &mut HashMap<usize, u8>) -> Option<Inferior> {
+    /// Attempts to start a new inferior process, stopped at its entry point. Returns
+    /// Some(Inferior) if successful, or None if an error is encountered.
+    pub fn new(target: &str, args: &Vec<String>, brk_point_map: &mut HashMap<usize, u8>)
+               -> Option<Inferior> {
         // TODO: implement me!
         let mut  cmd = Command::new(target);
         cmd.args(args);
@@ -55,23 +155,170 @@ impl Inferior {
             cmd.pre_exec(child_traceme);
         }
         let child = cmd.spawn().ok()?;
-        let mut the_inferior = Inferior{child, is_exited: false, is_brk_point: false, brk_point_rip: None };
+        let pid = Pid::from_raw(child.id() as i32);
+        let mut the_inferior = Inferior::with_pid(pid, false);
         the_inferior.wait(Some(WaitPidFlag::WUNTRACED)).ok()?;
+        ptrace::setoptions(pid, trace_options()).ok()?;
+        the_inferior.run_to_entry().ok()?;
+        brk_point_map.clear();
+
+        Some(the_inferior)
+    }
+
+    /// Attaches to the running process `pid` with PTRACE_ATTACH and waits for it to stop.
+    pub fn attach(pid: Pid, brk_point_map: &mut HashMap<usize, u8>)
+                  -> Result<(Inferior, Status), nix::Error> {
+        ptrace::attach(pid)?;
+        let mut the_inferior = Inferior::with_pid(pid, true);
+        let status = the_inferior.wait(None)?;
+        ptrace::setoptions(pid, trace_options())?;
+        the_inferior.attach_threads()?;
+        brk_point_map.clear();
+
+        Ok((the_inferior, status))
+    }
 
-        //set the break_point
-        for break_point in break_points {
-            let orig_byte = the_inferior.write_byte(break_point.clone(), 0xcc)
-                .ok().unwrap();
-            brk_point_map.insert(break_point.clone(), orig_byte);
+    fn with_pid(pid: Pid, attached: bool) -> Inferior {
+        Inferior {
+            pid,
+            tid: pid,
+            threads: vec![(1, pid)],
+            next_thread_num: 2,
+            attached,
+            is_exited: false,
+            is_brk_point: false,
+            brk_point_rip: None,
+            brk_point_tid: pid,
+            pending_signals: Vec::new(),
+            signal_table: SignalTable::default(),
+            early_stops: Vec::new(),
+            watch_hit: None,
+            hw_watches: Vec::new(),
+            fork_settings: ForkSettings::default(),
+            ptrace_event: None,
+            exec_pending: false,
+            held: Vec::new(),
+            vfork_parent: None,
+            vfork_brk_points_removed: false,
         }
+    }
 
-        Some(the_inferior)
+    /// Puts back the original bytes under our breakpoints, clears the watchpoints and lets the
+    /// process carry on without us. Processes held after a fork are let go as well.
+    pub fn detach(&mut self, brk_point_map: &mut HashMap<usize, u8>) -> Result<(), nix::Error> {
+        for tids in self.held.drain(..).chain(self.vfork_parent.take()) {
+            release(&tids, brk_point_map)?;
+        }
+        self.set_hw_watchpoints(&[])?;
+        release(&self.thread_ids(), brk_point_map)?;
+        brk_point_map.clear();
+        Ok(())
+    }
+
+    /// PTRACE_ATTACH only attaches to one thread, so attach to the others of the process too.
+    fn attach_threads(&mut self) -> Result<(), nix::Error> {
+        let tasks = std::fs::read_dir(format!("/proc/{}/task", self.pid))
+            .or(Err(nix::Error::Sys(nix::errno::Errno::ESRCH)))?;
+        for task in tasks.filter_map(|task| task.ok()) {
+            let tid = match task.file_name().to_str().and_then(|name| name.parse::<i32>().ok()) {
+                Some(tid) if tid != self.pid.as_raw() => Pid::from_raw(tid),
+                _ => continue,
+            };
+            ptrace::attach(tid)?;
+            waitpid(tid, Some(WaitPidFlag::__WALL))?;
+            ptrace::setoptions(tid, trace_options())?;
+            self.add_thread(tid, false)?;
+        }
+        Ok(())
+    }
+
+    /// Thread numbers and ids, oldest thread first.
+    pub fn threads(&self) -> &Vec<(usize, Pid)> {
+        &self.threads
+    }
+
+    /// The number and id of the current thread.
+    pub fn current_thread(&self) -> (usize, Pid) {
+        *self.threads.iter().find(|(_, tid)| *tid == self.tid).unwrap_or(&(0, self.tid))
+    }
+
+    /// Makes thread `num` the one registers, backtraces and stepping apply to. Returns its id,
+    /// or None if there is no such thread.
+    pub fn select_thread(&mut self, num: usize) -> Option<Pid> {
+        let (_, tid) = *self.threads.iter().find(|(thread_num, _)| *thread_num == num)?;
+        self.tid = tid;
+        Some(tid)
+    }
+
+    /// The name of a thread, as set with pthread_setname_np or inherited from the program.
+    pub fn thread_name(&self, tid: Pid) -> String {
+        std::fs::read_to_string(format!("/proc/{}/task/{}/comm", self.pid, tid))
+            .map_or(String::new(), |name| name.trim_end().to_string())
+    }
+
+    fn thread_ids(&self) -> Vec<Pid> {
+        self.threads.iter().map(|(_, tid)| *tid).collect()
+    }
+
+    fn is_thread(&self, tid: Pid) -> bool {
+        self.threads.iter().any(|(_, thread)| *thread == tid)
+    }
+
+    /// Starts tracking a new thread, which is stopped. It is resumed unless we are
+    /// single-stepping, when the other threads stay stopped too.
+    fn add_thread(&mut self, tid: Pid, step: bool) -> Result<(), nix::Error> {
+        println!("[New thread {} (LWP {})]", self.next_thread_num, tid);
+        self.threads.push((self.next_thread_num, tid));
+        self.next_thread_num += 1;
+        self.program_hw_watchpoints(tid)?;
+        if !step {
+            ptrace::cont(tid, None)?;
+        }
+        Ok(())
+    }
+
+    fn remove_thread(&mut self, tid: Pid) {
+        if let Some(pos) = self.threads.iter().position(|(_, thread)| *thread == tid) {
+            println!("[Thread {} (LWP {}) exited]", self.threads[pos].0, tid);
+            self.threads.remove(pos);
+        }
+        self.pending_signals.retain(|(thread, _)| *thread != tid);
+    }
+
+    pub fn set_fork_settings(&mut self, fork_settings: ForkSettings) {
+        self.fork_settings = fork_settings;
+    }
+
+    pub fn set_signal_table(&mut self, signal_table: SignalTable) {
+        self.signal_table = signal_table;
+    }
+
+    /// Remembers to deliver `signal` to `tid` when it resumes, if the program should get it.
+    fn keep_signal(&mut self, tid: Pid, signal: signal::Signal) {
+        if signal != signal::Signal::SIGTRAP && self.signal_table.policy(signal).pass {
+            self.pending_signals.push((tid, signal));
+        }
+    }
+
+    fn take_signal(&mut self, tid: Pid) -> Option<signal::Signal> {
+        let pos = self.pending_signals.iter().position(|(thread, _)| *thread == tid)?;
+        Some(self.pending_signals.remove(pos).1)
+    }
+
+    /// Returns true, once, after the inferior has exec'd. Its breakpoints went with the old
+    /// image.
+    pub fn take_exec(&mut self) -> bool {
+        std::mem::replace(&mut self.exec_pending, false)
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.attached
     }
 
 
     /// Returns the pid of this inferior.
     pub fn pid(&self) -> Pid {
-        nix::unistd::Pid::from_raw(self.child.id() as i32)
+        self.pid
     }
 
     /// Calls waitpid on this inferior and returns a Status to indicate the state of the process
@@ -82,50 +329,71 @@ impl Inferior {
                 self.is_exited = true;
                 Status::Exited(exit_code)
             },
-            WaitStatus::Signaled(_pid, signal, _core_dumped) => Status::Signaled(signal),
+            WaitStatus::Signaled(_pid, signal, _core_dumped) => {
+                self.is_exited = true;
+                Status::Signaled(signal)
+            },
             WaitStatus::Stopped(_pid, signal) => {
                 let regs = ptrace::getregs(self.pid())?;
                 Status::Stopped(signal, regs.rip as usize)
             }
+            WaitStatus::PtraceEvent(_pid, signal, event) => {
+                self.ptrace_event = Some(event);
+                let regs = ptrace::getregs(self.pid())?;
+                Status::Stopped(signal, regs.rip as usize)
+            }
             other => panic!("waitpid returned unexpected status: {:?}", other),
         })
     }
 
     pub fn continues(&mut self, brk_point_map : &HashMap<usize, u8>) -> Result<Status, nix::Error> {
-        if(self.is_brk_point) {
-            self.is_brk_point = false;
-            ptrace::step(self.pid(), None);
-            // todo: figure out the meaning of ptrace::step.
-            let status= self.wait(None)?;
-            match status.borrow() {
-                Status::Stopped(signal, rip) => {
-                    self.write_byte(self.brk_point_rip.unwrap(), 0xcc);
-                }
-                Status::Exited(_) => {
-                    return Ok(status);
+        // step over the breakpoint we are sitting on before putting the 0xcc back. After an exec
+        // the breakpoints in the map are the old program's, so there is nothing to step over.
+        let rip = ptrace::getregs(self.tid)?.rip as usize;
+        if !self.exec_pending
+            && (self.brk_point_hit().is_some() || brk_point_map.contains_key(&rip)) {
+            let status = self.step_instruction(brk_point_map)?;
+            match status {
+                Status::Stopped(signal::Signal::SIGTRAP, _) if self.watch_hit.is_none() => {}
+                _ => return Ok(status),
+            }
+        }
+        // the thread that hit the breakpoint may not be the current one any more. Others that
+        // hit a breakpoint meanwhile were rewound to hit it again, so they are left alone.
+        let current = self.tid;
+        let reported = self.brk_point_tid;
+        if reported != current && self.is_brk_point && self.is_thread(reported)
+            && !self.exec_pending {
+            let tid = reported;
+            if brk_point_map.contains_key(&(ptrace::getregs(tid)?.rip as usize)) {
+                self.tid = tid;
+                let status = self.step_instruction(brk_point_map);
+                self.tid = current;
+                if let Status::Stopped(signal, _) = status? {
+                    self.keep_signal(tid, signal);
                 }
-                _ => {}
             }
         }
 
-
-        ptrace::cont(self.pid(), None).unwrap();
-        let status = self.wait(None)?;
+        let status = self.resume(false, brk_point_map)?;
+        self.check_watch_hit(&status)?;
 
         match status.borrow() {
-            Status::Stopped(signal, rip) => {
+            Status::Stopped(signal::Signal::SIGTRAP, rip) if !self.exec_pending => {
                 let brk_point_rip = rip.clone() - 1;
                 let orig_byte_opt = brk_point_map.get(&brk_point_rip);
                 match orig_byte_opt {
                     Some(orig_byte) => {
-                        self.write_byte(brk_point_rip.clone(), orig_byte.clone());
+                        self.write_byte(brk_point_rip.clone(), orig_byte.clone())?;
                         self.is_brk_point = true;
+                        self.brk_point_tid = self.tid;
 
                         // set %rip = %rip - 1
-                        let mut regs = ptrace::getregs(self.pid())?;
+                        let mut regs = ptrace::getregs(self.tid)?;
                         regs.rip = brk_point_rip as u64;
                         self.brk_point_rip = Some(brk_point_rip);
-                        ptrace::setregs(self.pid(), regs)?;
+                        ptrace::setregs(self.tid, regs)?;
+                        return Ok(Status::Stopped(signal::Signal::SIGTRAP, brk_point_rip));
                     },
                     None => {}
                 }
@@ -136,63 +404,768 @@ impl Inferior {
         Ok(status)
     }
 
+    /// Executes a single instruction. If %rip sits on one of our breakpoints, the original byte
+    /// is put back for the step and the 0xcc is re-inserted afterwards.
+    pub fn step_instruction(&mut self, brk_point_map: &HashMap<usize, u8>) -> Result<Status, nix::Error> {
+        self.is_brk_point = false;
+        let rip = ptrace::getregs(self.tid)?.rip as usize;
+        if let Some(orig_byte) = brk_point_map.get(&rip) {
+            self.write_byte(rip, *orig_byte)?;
+        }
+        let status = self.resume(true, brk_point_map)?;
+        self.check_watch_hit(&status)?;
+        if let Status::Stopped(_, _) = status {
+            if brk_point_map.contains_key(&rip) && !self.exec_pending {
+                self.write_byte(rip, 0xcc)?;
+            }
+        }
+        Ok(status)
+    }
+
+    /// Runs until the next line of the current function, stepping over any calls. A call is
+    /// done when it returns with the stack pointer back at its frame's CFA, so recursive calls
+    /// returning to the same place don't count.
+    /// User breakpoints hit on the way stop it only if `should_stop` says so.
+    pub fn next(&mut self, debug_data: &DwarfData, brk_point_map: &mut HashMap<usize, u8>,
+                should_stop: &mut dyn FnMut(&Inferior) -> bool) -> Result<Status, nix::Error> {
+        self.step_line(debug_data, brk_point_map, false, should_stop)
+    }
+
+    /// Like next, but stops at the first line of a called function if it has debugging info.
+    /// Calls into library code are stepped over.
+    pub fn step(&mut self, debug_data: &DwarfData, brk_point_map: &mut HashMap<usize, u8>,
+                should_stop: &mut dyn FnMut(&Inferior) -> bool) -> Result<Status, nix::Error> {
+        self.step_line(debug_data, brk_point_map, true, should_stop)
+    }
+
+    fn step_line(&mut self, debug_data: &DwarfData, brk_point_map: &mut HashMap<usize, u8>,
+                 step_into: bool, should_stop: &mut dyn FnMut(&Inferior) -> bool)
+                 -> Result<Status, nix::Error> {
+        let start_rip = ptrace::getregs(self.tid)?.rip as usize;
+        let func = match debug_data.get_function_for_addr(start_rip) {
+            Some(func) => func,
+            None => return self.step_instruction(brk_point_map),
+        };
+        let start_line = debug_data.get_line_from_addr(start_rip).map(|line| line.number);
+
+        loop {
+            let regs = ptrace::getregs(self.tid)?;
+            let status = self.step_instruction(brk_point_map)?;
+            match status {
+                Status::Stopped(signal::Signal::SIGTRAP, _)
+                    if self.watch_hit.is_none() && !self.exec_pending => {}
+                _ => return Ok(status),
+            }
+            let mut rip = ptrace::getregs(self.tid)?.rip as usize;
+
+            if let Some(ret_addr) = self.called_from(regs.rip as usize, regs.rsp as usize)? {
+                if step_into && debug_data.get_function_from_addr(rip).is_some()
+                    && debug_data.get_line_at_addr(rip).is_some() {
+                    // stop where a breakpoint on the function would, once its frame is set up
+                    let entry = rip;
+                    let body = debug_data.skip_prologue(entry);
+                    while rip >= entry && rip < body {
+                        let status = self.step_instruction(brk_point_map)?;
+                        match status {
+                            Status::Stopped(signal::Signal::SIGTRAP, _)
+                                if self.watch_hit.is_none() && !self.exec_pending => {}
+                            _ => return Ok(status),
+                        }
+                        rip = ptrace::getregs(self.tid)?.rip as usize;
+                    }
+                    return Ok(Status::Stopped(signal::Signal::SIGTRAP, rip));
+                }
+                let status = self.run_to_return(ret_addr, regs.rsp as usize, brk_point_map,
+                                                should_stop)?;
+                if self.exec_pending || self.brk_point_hit() != Some(ret_addr) {
+                    // exited, exec'd, crashed or hit a user breakpoint inside the callee
+                    return Ok(status);
+                }
+                rip = ret_addr;
+            }
+
+            if !func.contains(rip) {
+                // we returned from the function
+                if debug_data.get_line_from_addr(rip).is_some() {
+                    return Ok(Status::Stopped(signal::Signal::SIGTRAP, rip));
+                }
+                loop {
+                    let status = self.continues(brk_point_map)?;
+                    if self.exec_pending || self.brk_point_hit().is_none() || should_stop(self) {
+                        return Ok(status);
+                    }
+                }
+            }
+            if let Some(line) = debug_data.get_line_at_addr(rip) {
+                if Some(line.number) != start_line {
+                    return Ok(Status::Stopped(signal::Signal::SIGTRAP, rip));
+                }
+            }
+        }
+    }
+
+    /// After single-stepping the instruction at `prev_rip` with the stack pointer at `prev_rsp`,
+    /// returns the return address if that instruction was a call.
+    fn called_from(&self, prev_rip: usize, prev_rsp: usize) -> Result<Option<usize>, nix::Error> {
+        let rsp = ptrace::getregs(self.tid)?.rsp as usize;
+        if rsp + 8 != prev_rsp {
+            return Ok(None);
+        }
+        let ret_addr = ptrace::read(self.tid, rsp as ptrace::AddressType)? as usize;
+        // a call instruction is at most 16 bytes long
+        if ret_addr > prev_rip && ret_addr <= prev_rip + 16 {
+            Ok(Some(ret_addr))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Continues until the call whose frame has the canonical frame address `frame_cfa` returns
+    /// to `ret_addr`. Returns early if anything else stops the inferior, except for user
+    /// breakpoints that `should_stop` says to go through.
+    pub fn run_to_return(&mut self, ret_addr: usize, frame_cfa: usize,
+                         brk_point_map: &mut HashMap<usize, u8>,
+                         should_stop: &mut dyn FnMut(&Inferior) -> bool)
+                         -> Result<Status, nix::Error> {
+        let tid = self.tid;
+        let inserted = !brk_point_map.contains_key(&ret_addr);
+        if inserted {
+            self.insert_brk_point(ret_addr, brk_point_map)?;
+        }
+        let status = loop {
+            let status = self.continues(brk_point_map)?;
+            if self.exec_pending {
+                break status;
+            }
+            match self.brk_point_hit() {
+                Some(addr) if addr == ret_addr => {
+                    // the stack pointer is back at the CFA once the call has returned, and lower
+                    // if a deeper recursive call returned to the same place. Other threads
+                    // running the same code don't count.
+                    if self.tid == tid && ptrace::getregs(self.tid)?.rsp as usize >= frame_cfa {
+                        break status;
+                    }
+                }
+                Some(_) if !should_stop(self) => {}
+                _ => break status,
+            }
+        };
+        if inserted && self.exec_pending {
+            // the address belongs to the new program now; leave its memory alone
+            brk_point_map.remove(&ret_addr);
+        } else if inserted && !self.is_exited() {
+            self.remove_brk_point(ret_addr, brk_point_map)?;
+        }
+        Ok(status)
+    }
+
+    /// Continues all threads, or single-steps the current one, and waits for a thread to stop.
+    /// Forks are dealt with here, according to the fork settings, and don't count as stops.
+    /// After a continue, the other threads are stopped too before we return.
+    fn resume(&mut self, step: bool, brk_point_map: &HashMap<usize, u8>)
+              -> Result<Status, nix::Error> {
+        if step {
+            let signal = self.take_signal(self.tid);
+            ptrace::step(self.tid, signal)?;
+        } else {
+            self.resume_all()?;
+        }
+        loop {
+            let status = self.wait_any(step)?;
+            match self.ptrace_event.take() {
+                Some(libc::PTRACE_EVENT_FORK) => self.follow_fork(false, brk_point_map)?,
+                Some(libc::PTRACE_EVENT_VFORK) => self.follow_fork(true, brk_point_map)?,
+                Some(libc::PTRACE_EVENT_VFORK_DONE) => {
+                    // the vfork child is gone from our memory, so our breakpoints can go back
+                    if self.vfork_brk_points_removed {
+                        for addr in brk_point_map.keys() {
+                            self.write_byte(*addr, 0xcc)?;
+                        }
+                        self.vfork_brk_points_removed = false;
+                    }
+                }
+                Some(libc::PTRACE_EVENT_EXEC) => {
+                    if let Some(parent) = self.vfork_parent.take() {
+                        release(&parent, brk_point_map)?;
+                    }
+                    // exec kills the other threads, and the one left takes over the leader's id
+                    self.tid = self.pid;
+                    let pid = self.pid;
+                    self.threads.retain(|(_, tid)| *tid == pid);
+                    self.pending_signals.clear();
+                    self.is_brk_point = false;
+                    self.exec_pending = true;
+                    return Ok(status);
+                }
+                _ => {
+                    match status {
+                        Status::Stopped(_, _) => {
+                            if !step {
+                                self.stop_others(brk_point_map)?;
+                            }
+                        }
+                        _ => {
+                            if let Some(parent) = self.vfork_parent.take() {
+                                release(&parent, brk_point_map)?;
+                            }
+                        }
+                    }
+                    return Ok(status);
+                }
+            }
+            // carry on where the fork or vfork left us
+            if step {
+                ptrace::step(self.tid, None)?;
+            } else {
+                ptrace::cont(self.tid, None)?;
+            }
+        }
+    }
+
+    /// Continues every thread, delivering the signals they are to be passed.
+    fn resume_all(&mut self) -> Result<(), nix::Error> {
+        for tid in self.thread_ids() {
+            if tid == self.tid {
+                continue;
+            }
+            let signal = self.take_signal(tid);
+            // the thread may have been killed from outside since it stopped
+            let _ = ptrace::cont(tid, signal);
+        }
+        let signal = self.take_signal(self.tid);
+        ptrace::cont(self.tid, signal)
+    }
+
+    /// Waits for any thread of the inferior to stop and makes it the current thread. Threads
+    /// being created or exiting don't count as stops, and neither do signals the signal table
+    /// says not to stop for.
+    fn wait_any(&mut self, step: bool) -> Result<Status, nix::Error> {
+        loop {
+            let wait_status = waitpid(None, Some(WaitPidFlag::__WALL))?;
+            let tid = match wait_status.pid() {
+                Some(tid) => tid,
+                None => continue,
+            };
+            if !self.is_thread(tid) {
+                match wait_status {
+                    // a new thread whose first stop beat the clone event
+                    WaitStatus::Stopped(_, _) if thread_group(tid) == Some(self.pid) => {
+                        self.add_thread(tid, step)?;
+                    }
+                    WaitStatus::Stopped(_, _) => self.early_stops.push(tid),
+                    // threads that went away with an exec, or processes we let go of
+                    _ => {}
+                }
+                continue;
+            }
+            match wait_status {
+                WaitStatus::Exited(_, exit_code) if tid == self.pid => {
+                    self.is_exited = true;
+                    return Ok(Status::Exited(exit_code));
+                }
+                WaitStatus::Signaled(_, signal, _) if tid == self.pid => {
+                    self.is_exited = true;
+                    return Ok(Status::Signaled(signal));
+                }
+                WaitStatus::Exited(_, _) | WaitStatus::Signaled(_, _, _) => {
+                    self.remove_thread(tid);
+                    if tid == self.tid && step {
+                        // the thread we were stepping is gone, and the others are stopped
+                        self.tid = self.threads[0].1;
+                        let regs = ptrace::getregs(self.tid)?;
+                        return Ok(Status::Stopped(signal::Signal::SIGTRAP, regs.rip as usize));
+                    }
+                }
+                WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_CLONE) => {
+                    let new_tid = Pid::from_raw(ptrace::getevent(tid)? as i32);
+                    if !self.is_thread(new_tid) {
+                        // the new thread starts out stopped by a SIGSTOP
+                        waitpid(new_tid, Some(WaitPidFlag::__WALL))?;
+                        self.add_thread(new_tid, step)?;
+                    }
+                    if step && tid == self.tid {
+                        ptrace::step(tid, None)?;
+                    } else {
+                        ptrace::cont(tid, None)?;
+                    }
+                }
+                WaitStatus::Stopped(_, signal) => {
+                    let policy = self.signal_table.policy(signal);
+                    if signal != signal::Signal::SIGTRAP && !policy.stop {
+                        if policy.print {
+                            println!("Program received signal {}, {}.", signal.as_str(),
+                                     signals::description(signal));
+                        }
+                        let pass = if policy.pass { Some(signal) } else { None };
+                        if step && tid == self.tid {
+                            ptrace::step(tid, pass)?;
+                        } else {
+                            ptrace::cont(tid, pass)?;
+                        }
+                        continue;
+                    }
+                    self.tid = tid;
+                    self.keep_signal(tid, signal);
+                    let regs = ptrace::getregs(tid)?;
+                    return Ok(Status::Stopped(signal, regs.rip as usize));
+                }
+                WaitStatus::PtraceEvent(_, signal, event) => {
+                    self.tid = tid;
+                    self.ptrace_event = Some(event);
+                    let regs = ptrace::getregs(tid)?;
+                    return Ok(Status::Stopped(signal, regs.rip as usize));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Stops every thread but the current one, so that the whole inferior holds still while we
+    /// look at it. A thread that hit a breakpoint meanwhile is rewound to hit it again later,
+    /// and other signals are kept for when the thread resumes if they are to be passed.
+    fn stop_others(&mut self, brk_point_map: &HashMap<usize, u8>) -> Result<(), nix::Error> {
+        let others: Vec<Pid> = self.thread_ids().into_iter().filter(|tid| *tid != self.tid).collect();
+        for tid in &others {
+            unsafe {
+                libc::syscall(libc::SYS_tgkill, self.pid.as_raw(), tid.as_raw(), libc::SIGSTOP);
+            }
+        }
+        for tid in others {
+            loop {
+                let wait_status = match waitpid(tid, Some(WaitPidFlag::__WALL)) {
+                    Ok(wait_status) => wait_status,
+                    Err(_) => {
+                        self.remove_thread(tid);
+                        break;
+                    }
+                };
+                match wait_status {
+                    WaitStatus::Stopped(_, signal::Signal::SIGSTOP) => break,
+                    WaitStatus::Stopped(_, signal::Signal::SIGTRAP) => {
+                        let mut regs = ptrace::getregs(tid)?;
+                        if brk_point_map.contains_key(&(regs.rip as usize - 1)) {
+                            regs.rip -= 1;
+                            ptrace::setregs(tid, regs)?;
+                        } else {
+                            // a watchpoint, which we'd rather not report later as if it were new
+                            write_debug_reg(tid, 6, 0)?;
+                        }
+                        ptrace::cont(tid, None)?;
+                    }
+                    WaitStatus::Stopped(_, signal) => {
+                        self.keep_signal(tid, signal);
+                        ptrace::cont(tid, None)?;
+                    }
+                    WaitStatus::PtraceEvent(_, _, event) => {
+                        // don't leave a child forked meanwhile stopped with our breakpoints in it
+                        if event == libc::PTRACE_EVENT_FORK || event == libc::PTRACE_EVENT_VFORK {
+                            let child = Pid::from_raw(ptrace::getevent(tid)? as i32);
+                            self.wait_new_process(child)?;
+                            release(&[child], brk_point_map)?;
+                        }
+                        ptrace::cont(tid, None)?;
+                    }
+                    WaitStatus::Exited(_, _) | WaitStatus::Signaled(_, _, _) => {
+                        self.remove_thread(tid);
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits for the first stop of a process just forked, unless wait_any saw it already.
+    fn wait_new_process(&mut self, pid: Pid) -> Result<(), nix::Error> {
+        match self.early_stops.iter().position(|early| *early == pid) {
+            Some(pos) => {
+                self.early_stops.remove(pos);
+            }
+            None => {
+                waitpid(pid, Some(WaitPidFlag::__WALL))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles a fork or vfork stop: follows the parent or the child, and detaches or holds the
+    /// other one. A detached process gets its memory back without our breakpoints.
+    fn follow_fork(&mut self, vfork: bool, brk_point_map: &HashMap<usize, u8>)
+                   -> Result<(), nix::Error> {
+        let kind = if vfork { "vfork" } else { "fork" };
+        let parent = self.pid;
+        let child = Pid::from_raw(ptrace::getevent(self.tid)? as i32);
+        // the child starts out stopped by a SIGSTOP
+        self.wait_new_process(child)?;
+        if self.fork_settings.follow_child {
+            println!("[Attaching after process {} {} to child process {}]", parent, kind, child);
+            // the parent's other threads are still running
+            self.stop_others(brk_point_map)?;
+            let parent_threads = self.thread_ids();
+            self.pid = child;
+            self.tid = child;
+            self.threads = vec![(1, child)];
+            self.next_thread_num = 2;
+            self.pending_signals.clear();
+            self.program_hw_watchpoints(child)?;
+            if vfork {
+                self.vfork_parent = Some(parent_threads);
+            } else if self.fork_settings.detach_on_fork {
+                println!("[Detaching after {} from parent process {}]", kind, parent);
+                release(&parent_threads, brk_point_map)?;
+            } else {
+                println!("[Holding parent process {} stopped]", parent);
+                self.held.push(parent_threads);
+            }
+        } else if self.fork_settings.detach_on_fork || vfork {
+            // a vfork parent can't run until its child execs or exits, so it can't be held
+            println!("[Detaching after {} from child process {}]", kind, child);
+            release(&[child], brk_point_map)?;
+            self.vfork_brk_points_removed = vfork && !brk_point_map.is_empty();
+        } else {
+            println!("[Holding child process {} stopped]", child);
+            self.held.push(vec![child]);
+        }
+        Ok(())
+    }
+
     pub fn kill(&mut self) {
+        for tids in self.held.drain(..).chain(self.vfork_parent.take()) {
+            if signal::kill(tids[0], signal::Signal::SIGKILL).is_ok() {
+                let _ = waitpid(tids[0], None);
+            }
+        }
         let pid = self.pid();
-        if self.child.kill().is_ok() {
+        if !self.is_exited && signal::kill(pid, signal::Signal::SIGKILL).is_ok() {
             self.wait(None).unwrap();
             println!("Killing running inferior (pid {})", pid);
         }
     }
 
-    pub fn print_backtrace(&self, debug_data: &DwarfData) -> Result<(), nix::Error> {
-        let user_regs = ptrace::getregs(self.pid())?;
-        let mut rbp = user_regs.rbp as usize;
-        let mut rip = user_regs.rip as usize;
-        let mut line_info;
-        let mut func_name;
-        while true {
-            match debug_data.get_line_from_addr(rip) {
-                Some(val) => {
-                    line_info = val;
-                },
-                None => {
-                    println!("Couldn't get line from addr.");
-                    return Ok(());
+    /// The siginfo of the signal the current thread stopped with.
+    pub fn siginfo(&self) -> Result<libc::siginfo_t, nix::Error> {
+        ptrace::getsiginfo(self.tid)
+    }
+
+    pub fn get_regs(&self) -> Result<libc::user_regs_struct, nix::Error> {
+        ptrace::getregs(self.tid)
+    }
+
+    /// Overwrites the inferior's registers. If this moves %rip off the breakpoint we are stopped
+    /// on, that breakpoint's 0xcc goes back in, since continues() won't step over it any more.
+    pub fn set_regs(&mut self, regs: libc::user_regs_struct, brk_point_map: &HashMap<usize, u8>)
+                    -> Result<(), nix::Error> {
+        if let Some(addr) = self.brk_point_hit() {
+            if regs.rip as usize != addr {
+                if brk_point_map.contains_key(&addr) {
+                    self.write_byte(addr, 0xcc)?;
                 }
+                self.is_brk_point = false;
             }
-            match debug_data.get_function_from_addr(rip) {
-                Some(val) => {
-                    func_name = val;
-                },
-                None => {
-                    println!("Couldn't get func name from addr.");
-                    return Ok(());
-                }
+        }
+        ptrace::setregs(self.tid, regs)
+    }
+
+    /// Unwinds the stack with the call frame information, innermost frame first, falling back to
+    /// the chain of saved frame pointers for code that has none. Stops after main, or when the
+    /// return address can't be recovered.
+    pub fn backtrace(&self, debug_data: &DwarfData) -> Result<Vec<Frame>, nix::Error> {
+        self.thread_backtrace(self.tid, debug_data)
+    }
+
+    /// Like backtrace, for any thread.
+    pub fn thread_backtrace(&self, tid: Pid, debug_data: &DwarfData)
+                            -> Result<Vec<Frame>, nix::Error> {
+        let mut regs = unwind::registers_from(&ptrace::getregs(tid)?);
+        let read = |addr: usize| {
+            ptrace::read(tid, addr as ptrace::AddressType)
+                .ok()
+                .map(|val| val as usize)
+        };
+        let mut frames: Vec<Frame> = Vec::new();
+        while let Some(pc) = regs[unwind::RA].filter(|pc| *pc != 0) {
+            // return addresses point after the call, which may already be the next line
+            let lookup_pc = if frames.is_empty() { pc } else { pc - 1 };
+            let function = debug_data.get_function_from_addr(lookup_pc);
+            let line = debug_data.get_line_from_addr(lookup_pc);
+            // without CFI, assume the function keeps a frame pointer
+            let unwound = debug_data.unwind(lookup_pc, &regs, &read).or_else(|| {
+                let at_entry = frames.is_empty() && at_function_entry(debug_data, pc);
+                unwind::unwind_with_frame_pointer(&regs, at_entry, &read)
+            });
+            let (cfa, caller) = match unwound {
+                Some(unwound) => unwound,
+                None => break,
+            };
+            // a caller's frame always lies above its callee's; anything else is garbage
+            if frames.last().map_or(false, |callee| cfa <= callee.cfa) {
+                break;
             }
-            println!("{} ({})", func_name, line_info);
-            if func_name == "main" {
+            // like gdb, don't show the libc frames that call main
+            let is_main = function.as_ref().map(|name| name.as_str()) == Some("main");
+            frames.push(Frame { pc, cfa, function, line });
+            if is_main || frames.len() >= MAX_FRAMES {
                 break;
             }
+            regs = caller;
+        }
+        Ok(frames)
+    }
+
+    /// Returns the address of the breakpoint the inferior is currently stopped on, if any.
+    pub fn brk_point_hit(&self) -> Option<usize> {
+        if self.is_brk_point && self.brk_point_tid == self.tid {
+            self.brk_point_rip
+        } else {
+            None
+        }
+    }
 
-            rip = ptrace::read(self.pid(), (rbp + 8) as ptrace::AddressType)? as usize;
-            rbp = ptrace::read(self.pid(), rbp as ptrace::AddressType)? as usize;
+    /// Returns the debug register slot of the watchpoint that stopped the inferior, if any.
+    pub fn watch_hit(&self) -> Option<usize> {
+        self.watch_hit
+    }
+
+    /// Programs DR0-DR3 with `watches`, given as (address, length, access). Lengths must be 1,
+    /// 2, 4 or 8 and addresses aligned to them. Slots past the end of `watches` are disabled.
+    pub fn set_hw_watchpoints(&mut self, watches: &[(usize, usize, WatchAccess)])
+                              -> Result<(), nix::Error> {
+        if watches.len() > NUM_HW_WATCHPOINTS {
+            return Err(nix::Error::Sys(nix::errno::Errno::ENOSPC));
+        }
+        self.hw_watches = watches.to_vec();
+        for tid in self.thread_ids() {
+            self.program_hw_watchpoints(tid)?;
         }
         Ok(())
     }
 
-    pub fn write_byte(&self, addr: usize, val: u8) -> Result<u8, nix::Error> {
+    /// Loads the watchpoints into the debug registers of one thread. New threads and forked
+    /// children start with their debug registers cleared, so they get this too.
+    fn program_hw_watchpoints(&self, tid: Pid) -> Result<(), nix::Error> {
+        // the kernel validates DR7 against the addresses, so disable everything first
+        write_debug_reg(tid, 7, 0)?;
+        let mut dr7 = 0;
+        for (slot, &(addr, len, access)) in self.hw_watches.iter().enumerate() {
+            let len_bits = match len {
+                1 => 0b00,
+                2 => 0b01,
+                4 => 0b11,
+                8 => 0b10,
+                _ => return Err(nix::Error::Sys(nix::errno::Errno::EINVAL)),
+            };
+            let rw_bits = match access {
+                WatchAccess::Write => 0b01,
+                WatchAccess::ReadWrite => 0b11,
+            };
+            write_debug_reg(tid, slot, addr)?;
+            // local enable bit, then the RW and LEN fields of the slot
+            dr7 |= 1 << (slot * 2);
+            dr7 |= (rw_bits | len_bits << 2) << (16 + slot * 4);
+        }
+        if dr7 != 0 {
+            write_debug_reg(tid, 7, dr7)?;
+        }
+        Ok(())
+    }
+
+    /// Records which watchpoint, if any, caused a SIGTRAP by decoding DR6, and clears it for the
+    /// next trap.
+    fn check_watch_hit(&mut self, status: &Status) -> Result<(), nix::Error> {
+        self.watch_hit = None;
+        if let Status::Stopped(signal::Signal::SIGTRAP, _) = status {
+            let dr6 = read_debug_reg(self.tid, 6)?;
+            if dr6 & DR6_HIT_MASK != 0 {
+                self.watch_hit = Some((dr6 & DR6_HIT_MASK).trailing_zeros() as usize);
+                write_debug_reg(self.tid, 6, 0)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Patches a 0xcc into the inferior at `addr` and records the original byte in
+    /// `brk_point_map`. Does nothing if a breakpoint is already installed there.
+    pub fn insert_brk_point(&mut self, addr: usize, brk_point_map: &mut HashMap<usize, u8>)
+                            -> Result<(), nix::Error> {
+        if brk_point_map.contains_key(&addr) {
+            return Ok(());
+        }
+        if self.brk_point_hit() == Some(addr) {
+            // We are stopped on this address with the original byte already restored;
+            // continues() puts the 0xcc back once it has stepped over the instruction.
+            let orig_byte = self.read_byte(addr)?;
+            brk_point_map.insert(addr, orig_byte);
+            return Ok(());
+        }
+        let orig_byte = self.write_byte(addr, 0xcc)?;
+        brk_point_map.insert(addr, orig_byte);
+        Ok(())
+    }
+
+    /// Restores the original byte at `addr` and forgets about the breakpoint.
+    pub fn remove_brk_point(&mut self, addr: usize, brk_point_map: &mut HashMap<usize, u8>)
+                            -> Result<(), nix::Error> {
+        if let Some(orig_byte) = brk_point_map.remove(&addr) {
+            self.write_byte(addr, orig_byte)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the address range of the main thread's stack, from /proc/<pid>/maps.
+    pub fn stack_range(&self) -> Option<(usize, usize)> {
+        let maps = std::fs::read_to_string(format!("/proc/{}/maps", self.pid())).ok()?;
+        let line = maps.lines().find(|line| line.ends_with("[stack]"))?;
+        let range = line.split_whitespace().next()?;
+        let mut bounds = range.split('-').map(|bound| usize::from_str_radix(bound, 16));
+        match (bounds.next(), bounds.next()) {
+            (Some(Ok(start)), Some(Ok(end))) => Some((start, end)),
+            _ => None,
+        }
+    }
+
+    /// The files mapped into the inferior, from /proc/<pid>/maps, with the address their first
+    /// page is mapped at.
+    pub fn mapped_files(&self) -> Vec<(String, usize)> {
+        let maps = match std::fs::read_to_string(format!("/proc/{}/maps", self.pid())) {
+            Ok(maps) => maps,
+            Err(_) => return Vec::new(),
+        };
+        let mut files: Vec<(String, usize)> = Vec::new();
+        for line in maps.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            // the mapping at file offset 0 tells where the file is loaded
+            if fields.len() < 6 || !fields[5].starts_with('/')
+                || usize::from_str_radix(fields[2], 16) != Ok(0) {
+                continue;
+            }
+            let path = fields[5..].join(" ");
+            let start = fields[0].split('-').next()
+                .and_then(|start| usize::from_str_radix(start, 16).ok());
+            if let Some(start) = start {
+                if !files.iter().any(|(file, _)| *file == path) {
+                    files.push((path, start));
+                }
+            }
+        }
+        files
+    }
+
+    /// Runs a program that has just been exec'd to its entry point, by which time the dynamic
+    /// loader has mapped the shared libraries it needs.
+    pub fn run_to_entry(&mut self) -> Result<(), nix::Error> {
+        let entry = match entry_point(self.pid) {
+            Some(entry) => entry,
+            None => return Ok(()),
+        };
+        let orig_byte = write_byte(self.pid, entry, 0xcc)?;
+        let mut signal = None;
+        loop {
+            ptrace::cont(self.pid, signal)?;
+            match self.wait(None)? {
+                Status::Stopped(signal::Signal::SIGTRAP, rip) if rip == entry + 1 => break,
+                // a signal can't be meant for the debugger before the program even started
+                Status::Stopped(sig, _) => {
+                    signal = if self.ptrace_event.take().is_some() { None } else { Some(sig) };
+                }
+                _ => return Ok(()),
+            }
+        }
+        write_byte(self.pid, entry, orig_byte)?;
+        let mut regs = ptrace::getregs(self.pid)?;
+        regs.rip = entry as u64;
+        ptrace::setregs(self.pid, regs)
+    }
+
+    pub fn read_byte(&self, addr: usize) -> Result<u8, nix::Error> {
         let aligned_addr = align_addr_to_word(addr);
         let byte_offset = addr - aligned_addr;
-        let word = ptrace::read(self.pid(), aligned_addr as ptrace::AddressType)? as u64;
-        let orig_byte = (word >> 8 * byte_offset) & 0xff;
-        let masked_word = word & !(0xff << 8 * byte_offset);
-        let updated_word = masked_word | ((val as u64) << 8 * byte_offset);
-        ptrace::write(
-            self.pid(),
-            aligned_addr as ptrace::AddressType,
-            updated_word as *mut std::ffi::c_void,
-        )?;
-        Ok(orig_byte as u8)
+        let word = ptrace::read(self.tid, aligned_addr as ptrace::AddressType)? as u64;
+        Ok(((word >> 8 * byte_offset) & 0xff) as u8)
+    }
+
+    /// Reads `len` bytes of the inferior's memory starting at `addr`, one word at a time.
+    pub fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        let start = align_addr_to_word(addr);
+        let mut bytes = Vec::with_capacity(len + size_of::<usize>() * 2);
+        let mut aligned_addr = start;
+        while aligned_addr < addr + len {
+            let word = ptrace::read(self.tid, aligned_addr as ptrace::AddressType)? as u64;
+            bytes.extend_from_slice(&word.to_le_bytes());
+            aligned_addr += size_of::<usize>();
+        }
+        let offset = addr - start;
+        Ok(bytes[offset..offset + len].to_vec())
+    }
+
+    /// Like read_memory, but shows the original bytes where we patched in breakpoints.
+    pub fn read_original_memory(&self, addr: usize, len: usize, brk_point_map: &HashMap<usize, u8>)
+                                -> Result<Vec<u8>, nix::Error> {
+        let mut bytes = self.read_memory(addr, len)?;
+        for (brk_addr, orig_byte) in brk_point_map {
+            if *brk_addr >= addr && *brk_addr < addr + len {
+                bytes[brk_addr - addr] = *orig_byte;
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Reads the NUL-terminated string at `addr`, one word at a time so that we stop at the end
+    /// of mapped memory, with the original bytes where breakpoints are patched in. Gives up after
+    /// `max` characters; the flag says whether the NUL was reached.
+    pub fn read_c_string(&self, addr: usize, max: usize, brk_point_map: &HashMap<usize, u8>)
+                         -> Result<(String, bool), nix::Error> {
+        let mut string = String::new();
+        let mut len = 0;
+        while len < max {
+            let chunk_len = (size_of::<usize>() - (addr + len) % size_of::<usize>()).min(max - len);
+            for byte in self.read_original_memory(addr + len, chunk_len, brk_point_map)? {
+                if byte == 0 {
+                    return Ok((string, true));
+                }
+                string.push(byte as char);
+                len += 1;
+            }
+        }
+        Ok((string, false))
+    }
+
+    /// Writes `bytes` into the inferior at `addr`, a word at a time. Where a breakpoint is
+    /// patched in, the new byte is recorded in `brk_point_map` as the original and the 0xcc stays.
+    pub fn write_memory(&mut self, addr: usize, bytes: &[u8],
+                        brk_point_map: &mut HashMap<usize, u8>) -> Result<(), nix::Error> {
+        let mut patched = bytes.to_vec();
+        for (i, byte) in bytes.iter().enumerate() {
+            if let Some(orig_byte) = brk_point_map.get_mut(&(addr + i)) {
+                *orig_byte = *byte;
+                // the breakpoint we are stopped on has its original byte in memory for now
+                if self.brk_point_hit() != Some(addr + i) {
+                    patched[i] = 0xcc;
+                }
+            }
+        }
+        let mut aligned_addr = align_addr_to_word(addr);
+        while aligned_addr < addr + bytes.len() {
+            let word = ptrace::read(self.tid, aligned_addr as ptrace::AddressType)? as u64;
+            let mut word_bytes = word.to_le_bytes();
+            for (i, word_byte) in word_bytes.iter_mut().enumerate() {
+                let byte_addr = aligned_addr + i;
+                if byte_addr >= addr && byte_addr < addr + bytes.len() {
+                    *word_byte = patched[byte_addr - addr];
+                }
+            }
+            ptrace::write(
+                self.tid,
+                aligned_addr as ptrace::AddressType,
+                u64::from_le_bytes(word_bytes) as *mut std::ffi::c_void,
+            )?;
+            aligned_addr += size_of::<usize>();
+        }
+        Ok(())
+    }
+
+    pub fn write_byte(&self, addr: usize, val: u8) -> Result<u8, nix::Error> {
+        write_byte(self.tid, addr, val)
     }
 
     pub fn is_exited(&self) -> bool {
@@ -201,6 +1174,91 @@ impl Inferior {
 
 }
 
+/// Writes a byte of `pid`'s memory and returns the byte that was there.
+fn write_byte(pid: Pid, addr: usize, val: u8) -> Result<u8, nix::Error> {
+    let aligned_addr = align_addr_to_word(addr);
+    let byte_offset = addr - aligned_addr;
+    let word = ptrace::read(pid, aligned_addr as ptrace::AddressType)? as u64;
+    let orig_byte = (word >> 8 * byte_offset) & 0xff;
+    let masked_word = word & !(0xff << 8 * byte_offset);
+    let updated_word = masked_word | ((val as u64) << 8 * byte_offset);
+    ptrace::write(
+        pid,
+        aligned_addr as ptrace::AddressType,
+        updated_word as *mut std::ffi::c_void,
+    )?;
+    Ok(orig_byte as u8)
+}
+
+/// The address `pid` starts executing at once loaded (AT_ENTRY in its auxiliary vector).
+fn entry_point(pid: Pid) -> Option<usize> {
+    let auxv = std::fs::read(format!("/proc/{}/auxv", pid)).ok()?;
+    let word = |bytes: &[u8]| {
+        let mut value = [0u8; 8];
+        value.copy_from_slice(bytes);
+        u64::from_ne_bytes(value)
+    };
+    auxv.chunks_exact(16)
+        .find(|entry| word(&entry[..8]) == libc::AT_ENTRY)
+        .map(|entry| word(&entry[8..]) as usize)
+}
+
+/// Puts the original bytes back under our breakpoints in a process and detaches from all of its
+/// threads `tids`.
+fn release(tids: &[Pid], brk_point_map: &HashMap<usize, u8>) -> Result<(), nix::Error> {
+    for (addr, orig_byte) in brk_point_map.iter() {
+        write_byte(tids[0], *addr, *orig_byte)?;
+    }
+    for tid in tids {
+        ptrace::detach(*tid, None)?;
+    }
+    Ok(())
+}
+
+fn read_debug_reg(tid: Pid, index: usize) -> Result<usize, nix::Error> {
+    let offset = DEBUG_REG_OFFSET + index * size_of::<usize>();
+    // PTRACE_PEEKUSER returns the value, so -1 is only an error if errno says so
+    let value = unsafe {
+        nix::errno::Errno::clear();
+        libc::ptrace(libc::PTRACE_PEEKUSER, tid.as_raw(), offset,
+                     std::ptr::null_mut::<libc::c_void>())
+    };
+    if value == -1 && nix::errno::errno() != 0 {
+        return Err(nix::Error::last());
+    }
+    Ok(value as usize)
+}
+
+fn write_debug_reg(tid: Pid, index: usize, value: usize) -> Result<(), nix::Error> {
+    let offset = DEBUG_REG_OFFSET + index * size_of::<usize>();
+    let ret = unsafe {
+        libc::ptrace(libc::PTRACE_POKEUSER, tid.as_raw(), offset, value)
+    };
+    nix::errno::Errno::result(ret).map(drop)
+}
+
+/// The process a thread belongs to, from /proc/<tid>/status.
+fn thread_group(tid: Pid) -> Option<Pid> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", tid)).ok()?;
+    let line = status.lines().find(|line| line.starts_with("Tgid:"))?;
+    line["Tgid:".len()..].trim().parse::<i32>().ok().map(Pid::from_raw)
+}
+
+/// Stop on new threads and forks, so nothing runs untraced with our breakpoints in it, and on
+/// exec.
+fn trace_options() -> ptrace::Options {
+    ptrace::Options::PTRACE_O_TRACECLONE
+        | ptrace::Options::PTRACE_O_TRACEFORK
+        | ptrace::Options::PTRACE_O_TRACEVFORK
+        | ptrace::Options::PTRACE_O_TRACEVFORKDONE
+        | ptrace::Options::PTRACE_O_TRACEEXEC
+}
+
+/// Whether `addr` is the first instruction of a function, before it has set up its frame.
+fn at_function_entry(debug_data: &DwarfData, addr: usize) -> bool {
+    debug_data.get_function_for_addr(addr).map_or(false, |func| func.address == addr)
+}
+
 fn align_addr_to_word(addr: usize) -> usize {
     addr & (-(size_of::<usize>() as isize) as usize)
 }
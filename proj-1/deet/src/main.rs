@@ -2,23 +2,58 @@ mod debugger;
 mod debugger_command;
 mod inferior;
 mod dwarf_data;
+mod crash;
+mod eval;
+mod examine;
 mod gimli_wrapper;
+mod registers;
+mod signals;
+mod unwind;
 
 use crate::debugger::Debugger;
 use nix::sys::signal::{signal, SigHandler, Signal};
+use nix::unistd::Pid;
 use std::env;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: {} <target program>", args[0]);
-        std::process::exit(1);
-    }
-    let target = &args[1];
+    let mut args: Vec<String> = env::args().collect();
+    // for unattended runs, where nobody is there to type `set crash-report-file`
+    let crash_report_file = if args.len() > 2 && args[1] == "--crash-report-file" {
+        args.remove(1);
+        Some(args.remove(1))
+    } else {
+        None
+    };
+    let pid = match args.len() {
+        2 => None,
+        3 if args[1] == "-p" => match args[2].parse::<i32>() {
+            Ok(pid) => Some(Pid::from_raw(pid)),
+            Err(_) => {
+                println!("Invalid process id {}", args[2]);
+                std::process::exit(1);
+            }
+        },
+        _ => {
+            println!("Usage: {} [--crash-report-file <path>] <target program>", args[0]);
+            println!("       {} [--crash-report-file <path>] -p <pid>", args[0]);
+            std::process::exit(1);
+        }
+    };
+    let target = match pid {
+        Some(pid) => inferior::executable_path(pid),
+        None => args[1].clone(),
+    };
 
     // Disable handling of ctrl+c in this process (so that ctrl+c only gets delivered to child
     // processes)
     unsafe { signal(Signal::SIGINT, SigHandler::SigIgn) }.expect("Error disabling SIGINT handling");
 
-    Debugger::new(target).run();
+    let mut debugger = Debugger::new(&target);
+    if let Some(path) = crash_report_file {
+        debugger.set_crash_report_file(&path);
+    }
+    if let Some(pid) = pid {
+        debugger.attach(pid);
+    }
+    debugger.run();
 }
@@ -0,0 +1,93 @@
+//! Access to the inferior's user registers by name, for `info registers`, `set $reg = value`
+//! and `$reg` in expressions.
+
+use libc::user_regs_struct;
+
+/// The registers `info registers` shows, in gdb's order.
+pub const REGISTER_NAMES: [&str; 24] = [
+    "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12",
+    "r13", "r14", "r15", "rip", "eflags", "cs", "ss", "ds", "es", "fs", "gs",
+];
+
+/// Names and bit positions of the eflags bits worth showing.
+const EFLAGS_BITS: [(&str, u32); 9] = [
+    ("CF", 0),
+    ("PF", 2),
+    ("AF", 4),
+    ("ZF", 6),
+    ("SF", 7),
+    ("TF", 8),
+    ("IF", 9),
+    ("DF", 10),
+    ("OF", 11),
+];
+
+/// Maps gdb's aliases for the instruction, stack and frame pointers to the real registers.
+pub fn canonical_name(name: &str) -> &str {
+    match name {
+        "pc" => "rip",
+        "sp" => "rsp",
+        "fp" => "rbp",
+        _ => name,
+    }
+}
+
+/// Returns a mutable reference to the register called `name`, or None if there's no such
+/// register.
+fn register_mut<'a>(regs: &'a mut user_regs_struct, name: &str) -> Option<&'a mut u64> {
+    Some(match canonical_name(name) {
+        "rax" => &mut regs.rax,
+        "rbx" => &mut regs.rbx,
+        "rcx" => &mut regs.rcx,
+        "rdx" => &mut regs.rdx,
+        "rsi" => &mut regs.rsi,
+        "rdi" => &mut regs.rdi,
+        "rbp" => &mut regs.rbp,
+        "rsp" => &mut regs.rsp,
+        "r8" => &mut regs.r8,
+        "r9" => &mut regs.r9,
+        "r10" => &mut regs.r10,
+        "r11" => &mut regs.r11,
+        "r12" => &mut regs.r12,
+        "r13" => &mut regs.r13,
+        "r14" => &mut regs.r14,
+        "r15" => &mut regs.r15,
+        "rip" => &mut regs.rip,
+        "eflags" => &mut regs.eflags,
+        "cs" => &mut regs.cs,
+        "ss" => &mut regs.ss,
+        "ds" => &mut regs.ds,
+        "es" => &mut regs.es,
+        "fs" => &mut regs.fs,
+        "gs" => &mut regs.gs,
+        "fs_base" => &mut regs.fs_base,
+        "gs_base" => &mut regs.gs_base,
+        _ => return None,
+    })
+}
+
+pub fn get_register(regs: &user_regs_struct, name: &str) -> Option<u64> {
+    let mut regs = *regs;
+    register_mut(&mut regs, name).map(|value| *value)
+}
+
+/// Sets the register called `name`. Returns false if there's no such register.
+pub fn set_register(regs: &mut user_regs_struct, name: &str, value: u64) -> bool {
+    match register_mut(regs, name) {
+        Some(register) => {
+            *register = value;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Formats eflags the way gdb does, e.g. `[ PF ZF IF ]`.
+pub fn format_eflags(eflags: u64) -> String {
+    let set: Vec<&str> = EFLAGS_BITS
+        .iter()
+        .filter(|(_, bit)| eflags & (1 << bit) != 0)
+        .map(|(name, _)| *name)
+        .collect();
+    format!("[ {} ]", set.join(" "))
+}
@@ -0,0 +1,135 @@
+//! What to do when the inferior gets a signal, as set with `handle`: whether to stop, whether to
+//! say so, and whether the signal is passed on to the program when it resumes.
+
+use nix::sys::signal::Signal;
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::str::FromStr;
+
+#[derive(Clone, Copy)]
+pub struct SignalPolicy {
+    pub stop: bool,
+    pub print: bool,
+    pub pass: bool,
+}
+
+/// Signals many programs get as part of their normal operation, which gdb lets through quietly.
+const QUIET_SIGNALS: [Signal; 7] = [
+    Signal::SIGALRM,
+    Signal::SIGURG,
+    Signal::SIGCHLD,
+    Signal::SIGWINCH,
+    Signal::SIGIO,
+    Signal::SIGVTALRM,
+    Signal::SIGPROF,
+];
+
+/// The actions `handle` understands.
+const KEYWORDS: [&str; 8] = ["stop", "nostop", "print", "noprint", "pass", "nopass", "ignore",
+    "noignore"];
+
+/// Signals meant for the debugger rather than the program: breakpoints and ctrl+c.
+const DEBUGGER_SIGNALS: [Signal; 2] = [Signal::SIGTRAP, Signal::SIGINT];
+
+#[derive(Clone)]
+pub struct SignalTable {
+    policies: HashMap<Signal, SignalPolicy>,
+}
+
+impl Default for SignalTable {
+    fn default() -> Self {
+        let policies = Signal::iterator()
+            .map(|signal| {
+                let policy = if QUIET_SIGNALS.contains(&signal) {
+                    SignalPolicy { stop: false, print: false, pass: true }
+                } else if DEBUGGER_SIGNALS.contains(&signal) {
+                    SignalPolicy { stop: true, print: true, pass: false }
+                } else {
+                    SignalPolicy { stop: true, print: true, pass: true }
+                };
+                (signal, policy)
+            })
+            .collect();
+        SignalTable { policies }
+    }
+}
+
+impl SignalTable {
+    pub fn policy(&self, signal: Signal) -> SignalPolicy {
+        self.policies[&signal]
+    }
+
+    /// Applies `handle <signals...> <keywords...>`, where a signal is a name with or without the
+    /// SIG prefix, or `all`. Returns the signals it applies to.
+    pub fn handle(&mut self, args: &[String]) -> Result<Vec<Signal>, String> {
+        let mut signals = Vec::new();
+        let mut keywords = Vec::new();
+        for arg in args {
+            if KEYWORDS.contains(&arg.as_str()) {
+                keywords.push(arg.as_str());
+            } else if arg == "all" {
+                // like gdb, leave the debugger's own signals alone
+                signals.extend(Signal::iterator().filter(|sig| !DEBUGGER_SIGNALS.contains(sig)));
+            } else if let Some(signal) = parse_signal(arg) {
+                signals.push(signal);
+            } else {
+                return Err(format!("Unrecognized or ambiguous flag word: \"{}\".", arg));
+            }
+        }
+        if signals.is_empty() {
+            return Err("Argument required (signal and action to apply).".to_string());
+        }
+        for keyword in keywords {
+            for signal in &signals {
+                let policy = self.policies.get_mut(signal).unwrap();
+                // stopping without saying so makes no sense, so each implies the other
+                match keyword {
+                    "stop" => {
+                        policy.stop = true;
+                        policy.print = true;
+                    }
+                    "nostop" => policy.stop = false,
+                    "print" => policy.print = true,
+                    "noprint" => {
+                        policy.print = false;
+                        policy.stop = false;
+                    }
+                    "pass" | "noignore" => policy.pass = true,
+                    // nopass and ignore
+                    _ => policy.pass = false,
+                }
+            }
+        }
+        Ok(signals)
+    }
+
+    /// Prints the policy of `signals` in gdb's format, or of every signal if it is empty.
+    pub fn print(&self, signals: &[Signal]) {
+        let all: Vec<Signal> = Signal::iterator().collect();
+        let signals = if signals.is_empty() { &all } else { signals };
+        println!("Signal        Stop\tPrint\tPass to program\tDescription");
+        for signal in signals {
+            let policy = self.policy(*signal);
+            println!("{:<14}{}\t{}\t{}\t\t{}", signal.as_str(), yes_no(policy.stop),
+                     yes_no(policy.print), yes_no(policy.pass), description(*signal));
+        }
+    }
+}
+
+/// Parses `SIGUSR1` or `USR1`.
+pub fn parse_signal(name: &str) -> Option<Signal> {
+    let name = name.to_uppercase();
+    let name = if name.starts_with("SIG") { name } else { format!("SIG{}", name) };
+    Signal::from_str(&name).ok()
+}
+
+/// The C library's description of a signal, e.g. "User defined signal 1".
+pub fn description(signal: Signal) -> String {
+    unsafe { CStr::from_ptr(libc::strsignal(signal as libc::c_int)) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn yes_no(flag: bool) -> &'static str {
+    if flag { "Yes" } else { "No" }
+}
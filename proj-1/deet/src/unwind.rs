@@ -0,0 +1,158 @@
+//! Stack unwinding with the call frame information (CFI) that compilers emit in .eh_frame and
+//! .debug_frame. For any pc, a row of the CFI table says how to compute the canonical frame
+//! address (CFA) and where the caller's registers were saved, whether or not the function keeps
+//! a frame pointer.
+
+use addr2line::gimli::{
+    BaseAddresses, CfaRule, DebugFrame, EhFrame, EndianRcSlice, Register, RegisterRule,
+    RunTimeEndian, UninitializedUnwindContext, UnwindSection, UnwindTableRow,
+};
+use object::{Object, ObjectSection};
+use std::rc::Rc;
+
+type Reader = EndianRcSlice<RunTimeEndian>;
+
+/// DWARF register numbers on x86-64. Column 16 holds the return address, so in a `Registers`
+/// it is the pc of that frame.
+pub const RBP: usize = 6;
+pub const RSP: usize = 7;
+pub const RA: usize = 16;
+const NUM_REGS: usize = 17;
+
+/// Register values of one frame indexed by DWARF register number, or None where they can't be
+/// recovered.
+pub type Registers = [Option<usize>; NUM_REGS];
+
+pub fn registers_from(regs: &libc::user_regs_struct) -> Registers {
+    let values = [
+        regs.rax, regs.rdx, regs.rcx, regs.rbx, regs.rsi, regs.rdi, regs.rbp, regs.rsp, regs.r8,
+        regs.r9, regs.r10, regs.r11, regs.r12, regs.r13, regs.r14, regs.r15, regs.rip,
+    ];
+    let mut registers: Registers = [None; NUM_REGS];
+    for (reg, value) in values.iter().enumerate() {
+        registers[reg] = Some(*value as usize);
+    }
+    registers
+}
+
+#[derive(Default)]
+pub struct CallFrameInfo {
+    eh_frame: Option<EhFrame<Reader>>,
+    debug_frame: Option<DebugFrame<Reader>>,
+    bases: BaseAddresses,
+}
+
+impl CallFrameInfo {
+    pub fn load(object: &object::File) -> CallFrameInfo {
+        let endian = if object.is_little_endian() {
+            RunTimeEndian::Little
+        } else {
+            RunTimeEndian::Big
+        };
+        let section_data = |name: &str| {
+            object
+                .section_data_by_name(name)
+                .filter(|data| !data.is_empty())
+                .map(|data| EndianRcSlice::new(Rc::from(&*data), endian))
+        };
+        let section_address =
+            |name: &str| object.section_by_name(name).map_or(0, |section| section.address());
+        // .eh_frame pointers may be relative to any of these
+        let bases = BaseAddresses::default()
+            .set_eh_frame(section_address(".eh_frame"))
+            .set_eh_frame_hdr(section_address(".eh_frame_hdr"))
+            .set_text(section_address(".text"))
+            .set_got(section_address(".got"));
+        CallFrameInfo {
+            eh_frame: section_data(".eh_frame").map(EhFrame::from),
+            debug_frame: section_data(".debug_frame").map(DebugFrame::from),
+            bases,
+        }
+    }
+
+    /// Returns the CFI row covering `addr`, looking in .eh_frame first.
+    fn find_row(&self, addr: usize) -> Option<UnwindTableRow<Reader>> {
+        let mut ctx = UninitializedUnwindContext::new();
+        if let Some(eh_frame) = &self.eh_frame {
+            if let Ok(row) = eh_frame.unwind_info_for_address(
+                &self.bases,
+                &mut ctx,
+                addr as u64,
+                EhFrame::cie_from_offset,
+            ) {
+                return Some(row);
+            }
+        }
+        if let Some(debug_frame) = &self.debug_frame {
+            if let Ok(row) = debug_frame.unwind_info_for_address(
+                &self.bases,
+                &mut ctx,
+                addr as u64,
+                DebugFrame::cie_from_offset,
+            ) {
+                return Some(row);
+            }
+        }
+        None
+    }
+
+    /// Computes the CFA of the frame executing at `lookup_pc` and the registers of its caller.
+    /// `read` reads a word of the inferior's memory. Returns None if there is no CFI for the pc.
+    pub fn unwind(
+        &self,
+        lookup_pc: usize,
+        regs: &Registers,
+        read: &dyn Fn(usize) -> Option<usize>,
+    ) -> Option<(usize, Registers)> {
+        let row = self.find_row(lookup_pc)?;
+        let cfa = match row.cfa() {
+            CfaRule::RegisterAndOffset { register, offset } => {
+                let base = (*regs.get(register.0 as usize)?)?;
+                (base as i64 + offset) as usize
+            }
+            // only used by PLT stubs and signal trampolines
+            CfaRule::Expression(_) => return None,
+        };
+        let mut caller: Registers = [None; NUM_REGS];
+        for reg in 0..NUM_REGS {
+            caller[reg] = match row.register(Register(reg as u16)) {
+                // registers the CFI doesn't mention are unchanged if the callee must preserve them
+                RegisterRule::Undefined if is_callee_saved(reg) => regs[reg],
+                RegisterRule::Undefined => None,
+                RegisterRule::SameValue => regs[reg],
+                RegisterRule::Offset(offset) => read((cfa as i64 + offset) as usize),
+                RegisterRule::ValOffset(offset) => Some((cfa as i64 + offset) as usize),
+                RegisterRule::Register(other) => *regs.get(other.0 as usize)?,
+                _ => None,
+            };
+        }
+        caller[RSP] = Some(cfa);
+        Some((cfa, caller))
+    }
+}
+
+/// Fallback for code without CFI: assume the function pushed %rbp and pointed %rbp at it, unless
+/// we are on its very first instruction (`at_entry`) and it hasn't done so yet.
+pub fn unwind_with_frame_pointer(
+    regs: &Registers,
+    at_entry: bool,
+    read: &dyn Fn(usize) -> Option<usize>,
+) -> Option<(usize, Registers)> {
+    let mut caller: Registers = [None; NUM_REGS];
+    let cfa = if at_entry {
+        caller[RBP] = regs[RBP];
+        regs[RSP]? + 8
+    } else {
+        let rbp = regs[RBP]?;
+        caller[RBP] = read(rbp);
+        rbp + 16
+    };
+    caller[RSP] = Some(cfa);
+    caller[RA] = read(cfa - 8);
+    Some((cfa, caller))
+}
+
+/// %rbx, %rbp and %r12-%r15 must be preserved across calls in the System V ABI.
+fn is_callee_saved(reg: usize) -> bool {
+    reg == 3 || reg == RBP || reg == RSP || (reg >= 12 && reg <= 15)
+}